@@ -0,0 +1,34 @@
+//! Connecting through a tower `ServiceBuilder`.
+//!
+//! This example connects to the server given in the argument list, giving up if the
+//! connection is not established within 5 seconds, sends a message and prints the
+//! answer.
+//!
+//! You can use this example together with the `echo-server` example.
+
+use std::{env, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    ConnectService,
+};
+use tower::{ServiceBuilder, ServiceExt};
+
+#[tokio::main]
+async fn main() {
+    let connect_addr =
+        env::args().nth(1).unwrap_or_else(|| panic!("this program requires at least one argument"));
+    let request = connect_addr.into_client_request().expect("Invalid URL");
+
+    let service = ServiceBuilder::new()
+        .timeout(Duration::from_secs(5))
+        .service(ConnectService::new(None, false));
+    let (mut ws_stream, _) = service.oneshot(request).await.expect("Failed to connect");
+    println!("WebSocket handshake has been successfully completed");
+
+    ws_stream.send(Message::text("Hello through tower")).await.expect("Failed to send");
+    if let Some(message) = ws_stream.next().await {
+        println!("{}", message.expect("Failed to receive"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Tunneling a byte stream through the binary messages of a WebSocket.
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{ready, Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tungstenite::{error::Error, Message};
+
+use crate::WebSocketStream;
+
+/// A byte stream on top of a `WebSocketStream`, e.g. to tunnel SSH or any other protocol
+/// over a WebSocket.
+///
+/// Every write is sent as a binary message, and reads return the payloads of the binary
+/// messages received one after another, regardless of how they are split into
+/// messages. Pings and pongs are skipped. A text message fails the read with an
+/// `io::Error` of kind `InvalidData`, since it has no place in a byte stream. The close
+/// frame of the peer, or the end of the connection, is the end of the stream.
+///
+/// Shutting the stream down performs the closing handshake. The errors of the
+/// `WebSocketStream` are returned as they are if they are `Error::Io`. Writing to a
+/// closed connection fails with kind `BrokenPipe`, and any other error has kind `Other`.
+#[derive(Debug)]
+pub struct WebSocketByteStream<S> {
+    inner: WebSocketStream<S>,
+    /// The payload being read, and how much of it has been read already.
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl<S> WebSocketByteStream<S> {
+    /// Read and write bytes through `inner`.
+    pub fn new(inner: WebSocketStream<S>) -> Self {
+        WebSocketByteStream { inner, chunk: Vec::new(), pos: 0 }
+    }
+
+    /// Returns a shared reference to the inner stream.
+    pub fn get_ref(&self) -> &WebSocketStream<S> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner stream.
+    pub fn get_mut(&mut self) -> &mut WebSocketStream<S> {
+        &mut self.inner
+    }
+
+    /// Returns the inner stream. The bytes of the payload being read, if any, are lost.
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.inner
+    }
+}
+
+impl<S> AsyncRead for WebSocketByteStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        while this.pos == this.chunk.len() {
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => {
+                    this.chunk = data;
+                    this.pos = 0;
+                }
+                Some(Ok(Message::Text(_))) => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "text message received");
+                    return Poll::Ready(Err(err));
+                }
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(io_error(e))),
+            }
+        }
+        let len = buf.remaining().min(this.chunk.len() - this.pos);
+        buf.put_slice(&this.chunk[this.pos..this.pos + len]);
+        this.pos += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WebSocketByteStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(Pin::new(&mut this.inner).poll_ready(cx)).map_err(io_error)?;
+        Pin::new(&mut this.inner).start_send(Message::Binary(buf.to_vec())).map_err(io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx).map_err(io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx).map_err(io_error)
+    }
+}
+
+fn io_error(e: Error) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        Error::ConnectionClosed | Error::AlreadyClosed => {
+            io::Error::new(io::ErrorKind::BrokenPipe, e.to_string())
+        }
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
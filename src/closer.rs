@@ -0,0 +1,125 @@
+//! Closing many streams at once, e.g. when shutting a server down.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Waker},
+};
+
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+/// A handle starting the closing handshake of all the streams registered with it, see
+/// `WebSocketStream::with_closer()`.
+///
+/// It can be cloned, e.g. to keep one in the task accepting the connections and one in
+/// the task waiting for Ctrl-C. Once `close()` has been called, the streams registered
+/// with it (including those registered afterwards) send a close frame the next time
+/// they are polled, and are woken up to that end. They then yield the messages received
+/// until the peer completes the handshake, like after `WebSocketStream::close()`.
+#[derive(Debug, Clone)]
+pub struct Closer {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    frame: CloseFrame<'static>,
+    closed: AtomicBool,
+    /// The wakers of the streams waiting for `close()`, by registration.
+    wakers: Mutex<Registrations>,
+}
+
+#[derive(Debug, Default)]
+struct Registrations {
+    next_id: u64,
+    wakers: HashMap<u64, Waker>,
+}
+
+impl Closer {
+    /// A closer sending close frames of code `CloseCode::Away` (1001), meant for a
+    /// server going down.
+    pub fn new() -> Self {
+        Closer::with_frame(CloseFrame { code: CloseCode::Away, reason: "".into() })
+    }
+
+    /// A closer sending `frame` as the close frame of every stream.
+    pub fn with_frame(frame: CloseFrame<'static>) -> Self {
+        let shared = Shared {
+            frame,
+            closed: AtomicBool::new(false),
+            wakers: Mutex::new(Registrations::default()),
+        };
+        Closer { shared: Arc::new(shared) }
+    }
+
+    /// Start the closing handshake of all the registered streams.
+    pub fn close(&self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        let mut registrations = self.shared.wakers.lock().unwrap_or_else(|e| e.into_inner());
+        let wakers = std::mem::take(&mut registrations.wakers);
+        drop(registrations);
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// Returns whether `close()` has been called.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn register(&self) -> CloserState {
+        let id = {
+            let mut registrations = self.shared.wakers.lock().unwrap_or_else(|e| e.into_inner());
+            registrations.next_id += 1;
+            registrations.next_id
+        };
+        CloserState { shared: self.shared.clone(), id, done: false }
+    }
+}
+
+impl Default for Closer {
+    fn default() -> Self {
+        Closer::new()
+    }
+}
+
+/// The registration of a stream with a `Closer`.
+#[derive(Debug)]
+pub(crate) struct CloserState {
+    shared: Arc<Shared>,
+    id: u64,
+    /// Whether the close frame has been handed out already.
+    done: bool,
+}
+
+impl CloserState {
+    /// Returns the close frame to send once the closer has been triggered, only once,
+    /// otherwise makes sure the task is woken up when that happens.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Option<CloseFrame<'static>> {
+        if self.done {
+            return None;
+        }
+        if !self.shared.closed.load(Ordering::SeqCst) {
+            let mut registrations = self.shared.wakers.lock().unwrap_or_else(|e| e.into_inner());
+            registrations.wakers.insert(self.id, cx.waker().clone());
+            drop(registrations);
+            // `close()` may have taken the wakers in the meantime.
+            if !self.shared.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+        }
+        self.done = true;
+        Some(self.shared.frame.clone())
+    }
+}
+
+impl Drop for CloserState {
+    fn drop(&mut self) {
+        if let Ok(mut registrations) = self.shared.wakers.lock() {
+            registrations.wakers.remove(&self.id);
+        }
+    }
+}
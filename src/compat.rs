@@ -43,6 +43,10 @@ pub(crate) struct AllowStd<S> {
     // (and handshaking), the write_waker slots for the Sink impl.
     write_waker_proxy: Arc<WakerProxy>,
     read_waker_proxy: Arc<WakerProxy>,
+    // The number of bytes written so far, to tell whether writing makes progress.
+    written: u64,
+    // The number of bytes that may still be read, bounding the size of the handshake.
+    read_limit: Option<usize>,
 }
 
 // Internal trait used only in the Handshake module for registering
@@ -66,6 +70,8 @@ impl<S> AllowStd<S> {
             inner,
             write_waker_proxy: Default::default(),
             read_waker_proxy: Default::default(),
+            written: 0,
+            read_limit: None,
         };
 
         // Register the handshake waker as read waker for both proxies,
@@ -139,6 +145,33 @@ where
     pub(crate) fn get_ref(&self) -> &S {
         &self.inner
     }
+
+    pub(crate) fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Fail with `ReadLimitExceeded` once more than `limit` bytes have been read.
+    pub(crate) fn set_read_limit(&mut self, limit: Option<usize>) {
+        self.read_limit = limit;
+    }
+}
+
+/// The error of reading more than allowed by `AllowStd::set_read_limit()`.
+#[derive(Debug)]
+pub(crate) struct ReadLimitExceeded;
+
+impl std::fmt::Display for ReadLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("read limit exceeded")
+    }
+}
+
+impl std::error::Error for ReadLimitExceeded {}
+
+impl ReadLimitExceeded {
+    pub(crate) fn is(err: &std::io::Error) -> bool {
+        err.get_ref().map_or(false, |err| err.is::<ReadLimitExceeded>())
+    }
 }
 
 impl<S> Read for AllowStd<S>
@@ -147,12 +180,25 @@ where
 {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         trace!("{}:{} Read.read", file!(), line!());
+        let buf = match self.read_limit {
+            Some(0) if !buf.is_empty() => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, ReadLimitExceeded))
+            }
+            Some(limit) => &mut buf[..limit.min(buf.len())],
+            None => buf,
+        };
         let mut buf = ReadBuf::new(buf);
         match self.with_context(ContextWaker::Read, |ctx, stream| {
             trace!("{}:{} Read.with_context read -> poll_read", file!(), line!());
             stream.poll_read(ctx, &mut buf)
         }) {
-            Poll::Ready(Ok(_)) => Ok(buf.filled().len()),
+            Poll::Ready(Ok(_)) => {
+                let n = buf.filled().len();
+                if let Some(limit) = self.read_limit.as_mut() {
+                    *limit -= n;
+                }
+                Ok(n)
+            }
             Poll::Ready(Err(err)) => Err(err),
             Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
         }
@@ -169,7 +215,11 @@ where
             trace!("{}:{} Write.with_context write -> poll_write", file!(), line!());
             stream.poll_write(ctx, buf)
         }) {
-            Poll::Ready(r) => r,
+            Poll::Ready(Ok(n)) => {
+                self.written += n as u64;
+                Ok(n)
+            }
+            Poll::Ready(Err(err)) => Err(err),
             Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
         }
     }
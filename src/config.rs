@@ -0,0 +1,96 @@
+//! Building a `WebSocketConfig`.
+use tungstenite::protocol::WebSocketConfig;
+
+/// A builder of `WebSocketConfig`, to set some of its fields and keep the default values
+/// of the others.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), tokio_tungstenite::tungstenite::Error> {
+/// use tokio_tungstenite::{connect_async_with_config, WebSocketConfigBuilder};
+///
+/// let config = WebSocketConfigBuilder::new().max_message_size(Some(1 << 20)).build();
+/// let (stream, _) = connect_async_with_config("ws://localhost:9001/", Some(config), false).await?;
+/// # drop(stream);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebSocketConfigBuilder {
+    config: WebSocketConfig,
+}
+
+impl WebSocketConfigBuilder {
+    /// Start from the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum size of an incoming message, `None` for no limit.
+    pub fn max_message_size(mut self, max_message_size: Option<usize>) -> Self {
+        self.config.max_message_size = max_message_size;
+        self
+    }
+
+    /// Set the maximum size of a single incoming frame, `None` for no limit.
+    pub fn max_frame_size(mut self, max_frame_size: Option<usize>) -> Self {
+        self.config.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Set how many bytes of outgoing messages are buffered before writing them to the
+    /// stream, see `WebSocketConfig::write_buffer_size`.
+    pub fn write_buffer_size(mut self, write_buffer_size: usize) -> Self {
+        self.config.write_buffer_size = write_buffer_size;
+        self
+    }
+
+    /// Set the maximum size of the write buffer, see
+    /// `WebSocketConfig::max_write_buffer_size`.
+    pub fn max_write_buffer_size(mut self, max_write_buffer_size: usize) -> Self {
+        self.config.max_write_buffer_size = max_write_buffer_size;
+        self
+    }
+
+    /// Set whether a server accepts unmasked frames from clients, which RFC 6455
+    /// forbids.
+    ///
+    /// **This is not compliant with the protocol** and only meant for peers known to
+    /// misbehave, or for experiments over trusted links. Clients always mask their
+    /// frames: tungstenite provides no way to disable it, this setting only affects
+    /// servers.
+    pub fn accept_unmasked_frames(mut self, accept_unmasked_frames: bool) -> Self {
+        self.config.accept_unmasked_frames = accept_unmasked_frames;
+        self
+    }
+
+    /// Returns the configuration.
+    pub fn build(self) -> WebSocketConfig {
+        self.config
+    }
+}
+
+impl From<WebSocketConfigBuilder> for WebSocketConfig {
+    fn from(builder: WebSocketConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebSocketConfigBuilder;
+    use tungstenite::protocol::WebSocketConfig;
+
+    #[test]
+    fn builder_keeps_defaults() {
+        let default = WebSocketConfig::default();
+        let config = WebSocketConfigBuilder::new()
+            .max_message_size(Some(1024))
+            .accept_unmasked_frames(true)
+            .build();
+        assert_eq!(config.max_message_size, Some(1024));
+        assert!(config.accept_unmasked_frames);
+        assert_eq!(config.max_frame_size, default.max_frame_size);
+        assert_eq!(config.write_buffer_size, default.write_buffer_size);
+        assert_eq!(config.max_write_buffer_size, default.max_write_buffer_size);
+    }
+}
@@ -0,0 +1,371 @@
+//! Establishing TCP connections to the resolved addresses of a host.
+use std::{
+    future::Future,
+    io,
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
+
+use futures_util::{future::poll_fn, stream::FuturesUnordered, StreamExt};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+
+/// The future returned by `Resolver::resolve()`.
+pub type Resolving = Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send>>;
+
+/// Name resolution used by the `connect()` helpers to get the addresses of a host.
+///
+/// Implement it to use something else than the system resolver, e.g. DNS over HTTPS,
+/// split-horizon DNS or a static hosts map.
+pub trait Resolver: Send + Sync {
+    /// Resolve `host` to the socket addresses to connect to, `port` is the port
+    /// of the destination. IPv6 addresses are passed without the brackets.
+    fn resolve(&self, host: &str, port: u16) -> Resolving;
+}
+
+/// The resolver of the operating system, i.e. `tokio::net::lookup_host()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Resolving {
+        let host = host.to_owned();
+        Box::pin(async move { Ok(lookup_host((host.as_str(), port)).await?.collect()) })
+    }
+}
+
+/// Options of the TCP sockets used by the `connect()` helpers, applied before connecting.
+///
+/// Errors setting the options are returned as is. Options the platform has no notion
+/// of are documented as ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketConfig {
+    /// Enable the TCP keepalive (`SO_KEEPALIVE`) with the given parameters.
+    pub keepalive: Option<TcpKeepalive>,
+    /// Set `SO_REUSEADDR`.
+    pub reuse_address: bool,
+    /// The size of the send buffer (`SO_SNDBUF`).
+    pub send_buffer_size: Option<usize>,
+    /// The size of the receive buffer (`SO_RCVBUF`).
+    pub recv_buffer_size: Option<usize>,
+}
+
+/// The parameters of the TCP keepalive, the defaults of the system are used for the
+/// ones left to `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpKeepalive {
+    /// How long the connection must be idle before the first probe is sent.
+    pub idle: Option<Duration>,
+    /// The interval between two probes. Ignored on platforms other than Linux,
+    /// Android, FreeBSD, the Apple ones and Windows.
+    pub interval: Option<Duration>,
+    /// The number of unanswered probes after which the connection is dropped. Ignored
+    /// on platforms other than Linux, Android, FreeBSD and the Apple ones.
+    pub retries: Option<u32>,
+}
+
+impl SocketConfig {
+    fn apply(&self, socket: &TcpSocket) -> io::Result<()> {
+        let socket = socket2::SockRef::from(socket);
+        if self.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(keepalive) = self.keepalive {
+            socket.set_tcp_keepalive(&keepalive.params())?;
+        }
+        Ok(())
+    }
+}
+
+impl TcpKeepalive {
+    fn params(&self) -> socket2::TcpKeepalive {
+        let mut params = socket2::TcpKeepalive::new();
+        if let Some(idle) = self.idle {
+            params = params.with_time(idle);
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_vendor = "apple",
+            windows
+        ))]
+        {
+            if let Some(interval) = self.interval {
+                params = params.with_interval(interval);
+            }
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_vendor = "apple"
+        ))]
+        {
+            if let Some(retries) = self.retries {
+                params = params.with_retries(retries);
+            }
+        }
+        params
+    }
+}
+
+/// An address family, see `ClientBuilder::address_family()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// IPv4 addresses.
+    Ipv4,
+    /// IPv6 addresses.
+    Ipv6,
+}
+
+/// How `connect()` establishes the connection.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Dial<'a> {
+    /// The resolver to use instead of the `SystemResolver`.
+    pub(crate) resolver: Option<&'a dyn Resolver>,
+    /// Race the connection attempts instead of trying the addresses one by one.
+    pub(crate) happy_eyeballs: bool,
+    /// The local address to bind the socket to before connecting.
+    pub(crate) local_addr: Option<SocketAddr>,
+    /// The options to set on the socket before connecting.
+    pub(crate) socket: Option<&'a SocketConfig>,
+    /// The address family to try first.
+    pub(crate) address_family: Option<AddressFamily>,
+}
+
+/// Resolve `host` and connect to one of its addresses.
+///
+/// Without `happy_eyeballs` the addresses are tried one after another, just like
+/// `TcpStream::connect()` does.
+pub(crate) async fn connect(host: &str, port: u16, dial: Dial<'_>) -> io::Result<TcpStream> {
+    // Hosts of URIs keep the brackets around IPv6 addresses.
+    let host = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    let (host, scoped) = match scoped_ipv6(host, port) {
+        Scoped::Addr(addr) => (host.to_owned(), Some(addr)),
+        Scoped::Name(host) => (host, None),
+    };
+    let mut addrs = match scoped {
+        Some(addr) => vec![addr],
+        None => dial.resolver.unwrap_or(&SystemResolver).resolve(&host, port).await?,
+    };
+
+    if let Some(local_addr) = dial.local_addr {
+        if !addrs.is_empty() {
+            addrs.retain(|addr| addr.is_ipv4() == local_addr.is_ipv4());
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{host} has no address of the same family as {local_addr}"),
+                ));
+            }
+        }
+    }
+
+    if let Some(family) = dial.address_family {
+        prefer(&mut addrs, family);
+    }
+
+    if dial.happy_eyeballs {
+        return self::happy_eyeballs(addrs, dial).await;
+    }
+
+    let mut last_error = None;
+    for addr in addrs {
+        match connect_addr(addr, dial).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(no_addresses))
+}
+
+/// A host that is an IPv6 address with a zone identifier, e.g. `fe80::1%25eth0`.
+enum Scoped {
+    /// The zone is numeric, so the address can be used right away.
+    Addr(SocketAddr),
+    /// The zone is the name of an interface (or there is no zone), the host must be
+    /// resolved, with the zone identifier decoded, e.g. as `fe80::1%eth0`.
+    Name(String),
+}
+
+/// Parse the zone identifier of IPv6 addresses, which URIs encode as `%25<zone>`
+/// (RFC 6874). Any other host, including one with a plain `%<zone>`, is resolved as is,
+/// since a zone starting with `25` would make the two forms ambiguous.
+fn scoped_ipv6(host: &str, port: u16) -> Scoped {
+    let (addr, zone) = match host.split_once("%25") {
+        Some(split) => split,
+        None => return Scoped::Name(host.to_owned()),
+    };
+    match (addr.parse::<Ipv6Addr>(), zone.parse::<u32>()) {
+        (Ok(addr), Ok(scope_id)) => Scoped::Addr(SocketAddrV6::new(addr, port, 0, scope_id).into()),
+        _ => Scoped::Name(format!("{addr}%{zone}")),
+    }
+}
+
+/// Connect to `addr`, setting up the socket as requested by `dial` first.
+pub(crate) async fn connect_addr(addr: SocketAddr, dial: Dial<'_>) -> io::Result<TcpStream> {
+    if dial.local_addr.is_none() && dial.socket.is_none() {
+        return TcpStream::connect(addr).await;
+    }
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    if let Some(config) = dial.socket {
+        config.apply(&socket)?;
+    }
+    if let Some(local_addr) = dial.local_addr {
+        socket.bind(local_addr)?;
+    }
+    socket.connect(addr).await
+}
+
+fn no_addresses() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")
+}
+
+/// Delay between two connection attempts, as recommended by RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+enum Event {
+    Attempted(io::Result<TcpStream>),
+    DelayElapsed,
+}
+
+/// Connect to one of `addrs` the "Happy Eyeballs" way (RFC 8305).
+///
+/// The addresses are attempted in an order alternating between IPv6 and IPv4. A new
+/// attempt is started whenever the previous one fails or does not succeed within
+/// `CONNECTION_ATTEMPT_DELAY`, the first established connection wins and all other
+/// attempts are cancelled. If every attempt fails, the last error is returned.
+async fn happy_eyeballs(addrs: Vec<SocketAddr>, dial: Dial<'_>) -> io::Result<TcpStream> {
+    let mut remaining = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match remaining.next() {
+                Some(addr) => attempts.push(connect_addr(addr, dial)),
+                None => return Err(last_error.unwrap_or_else(no_addresses)),
+            }
+        }
+
+        let delay = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY);
+        tokio::pin!(delay);
+        let event = poll_fn(|cx| {
+            if let Poll::Ready(Some(result)) = attempts.poll_next_unpin(cx) {
+                return Poll::Ready(Event::Attempted(result));
+            }
+            if delay.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Event::DelayElapsed);
+            }
+            Poll::Pending
+        })
+        .await;
+
+        match event {
+            // Dropping the remaining attempts closes their sockets.
+            Event::Attempted(Ok(stream)) => return Ok(stream),
+            Event::Attempted(Err(err)) => {
+                log::debug!("Connection attempt failed: {err}");
+                last_error = Some(err);
+            }
+            Event::DelayElapsed => {}
+        }
+        if let Some(addr) = remaining.next() {
+            attempts.push(connect_addr(addr, dial));
+        }
+    }
+}
+
+/// Move the addresses of `family` first, keeping the order of the addresses otherwise.
+fn prefer(addrs: &mut [SocketAddr], family: AddressFamily) {
+    addrs.sort_by_key(|addr| addr.is_ipv6() != (family == AddressFamily::Ipv6));
+}
+
+/// Reorder the addresses so that the address families alternate, starting with the
+/// family of the first address.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().map_or(true, SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|addr| addr.is_ipv6() == first_is_ipv6);
+
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => {
+                result.extend(a);
+                result.extend(b);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interleave, prefer, scoped_ipv6, AddressFamily, Scoped};
+    use std::net::SocketAddr;
+
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_families() {
+        let input = addrs(&["[::1]:80", "[::2]:80", "[::3]:80", "127.0.0.1:80", "127.0.0.2:80"]);
+        let expected = addrs(&["[::1]:80", "127.0.0.1:80", "[::2]:80", "127.0.0.2:80", "[::3]:80"]);
+        assert_eq!(interleave(input), expected);
+
+        let input = addrs(&["127.0.0.1:80", "[::1]:80"]);
+        assert_eq!(interleave(input.clone()), input);
+
+        // Only one family available.
+        let input = addrs(&["127.0.0.1:80", "127.0.0.2:80"]);
+        assert_eq!(interleave(input.clone()), input);
+        assert_eq!(interleave(Vec::new()), Vec::new());
+    }
+
+    #[test]
+    fn preferred_family() {
+        let mut input = addrs(&["[::1]:80", "127.0.0.1:80", "[::2]:80", "127.0.0.2:80"]);
+        prefer(&mut input, AddressFamily::Ipv4);
+        assert_eq!(input, addrs(&["127.0.0.1:80", "127.0.0.2:80", "[::1]:80", "[::2]:80"]));
+        prefer(&mut input, AddressFamily::Ipv6);
+        assert_eq!(input, addrs(&["[::1]:80", "[::2]:80", "127.0.0.1:80", "127.0.0.2:80"]));
+    }
+
+    #[test]
+    fn zone_identifiers() {
+        for (host, scope_id) in [("fe80::1%252", 2), ("fe80::1%2525", 25), ("fe80::1%25250", 250)] {
+            match scoped_ipv6(host, 9000) {
+                Scoped::Addr(SocketAddr::V6(addr)) => {
+                    assert_eq!(addr.ip(), &"fe80::1".parse::<std::net::Ipv6Addr>().unwrap());
+                    assert_eq!((addr.port(), addr.scope_id()), (9000, scope_id));
+                }
+                _ => panic!("{host} is not a scoped address"),
+            }
+        }
+        for (host, name) in [
+            ("fe80::1%25eth0", "fe80::1%eth0"),
+            ("fe80::1%2525eth0", "fe80::1%25eth0"),
+            ("fe80::1%eth0", "fe80::1%eth0"),
+            ("fe80::1%2", "fe80::1%2"),
+            ("fe80::1", "fe80::1"),
+            ("example.com", "example.com"),
+        ] {
+            match scoped_ipv6(host, 9000) {
+                Scoped::Name(resolved) => assert_eq!(resolved, name),
+                Scoped::Addr(addr) => panic!("{host} resolved to {addr}"),
+            }
+        }
+    }
+}
@@ -2,6 +2,8 @@
 use crate::compat::SetWaker;
 use crate::{compat::AllowStd, WebSocketStream};
 use log::*;
+#[cfg(feature = "handshake")]
+use std::sync::{Arc, Mutex};
 use std::{
     future::Future,
     io::{Read, Write},
@@ -13,9 +15,11 @@ use tungstenite::WebSocket;
 #[cfg(feature = "handshake")]
 use tungstenite::{
     handshake::{
-        client::Response, server::Callback, HandshakeError as Error, HandshakeRole,
-        MidHandshake as WsHandshake,
+        client::Response,
+        server::{self, Callback},
+        HandshakeError as Error, HandshakeRole, MidHandshake as WsHandshake,
     },
+    http::{HeaderMap, StatusCode},
     ClientHandshake, ServerHandshake,
 };
 
@@ -54,6 +58,92 @@ where
     }
 }
 
+/// What has been negotiated during the server handshake.
+#[cfg(feature = "handshake")]
+#[derive(Debug, Default)]
+pub(crate) struct Negotiated {
+    pub(crate) subprotocol: Option<String>,
+    pub(crate) request_headers: Option<HeaderMap>,
+}
+
+/// A callback recording what has been negotiated by the callback it wraps, so that it
+/// is available once the handshake is complete.
+#[cfg(feature = "handshake")]
+pub(crate) struct RecordingCallback<C> {
+    pub(crate) inner: C,
+    pub(crate) negotiated: Arc<Mutex<Negotiated>>,
+    /// Whether to record a copy of the headers of the request.
+    pub(crate) keep_request_headers: bool,
+}
+
+#[cfg(feature = "handshake")]
+impl<C: Callback> Callback for RecordingCallback<C> {
+    fn on_request(
+        self,
+        request: &server::Request,
+        response: server::Response,
+    ) -> Result<server::Response, server::ErrorResponse> {
+        let response = self.inner.on_request(request, response)?;
+        if let Ok(mut negotiated) = self.negotiated.lock() {
+            negotiated.subprotocol = subprotocol(response.headers());
+            if self.keep_request_headers {
+                negotiated.request_headers = Some(request.headers().clone());
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// A callback choosing the subprotocol of the connection among those offered by the
+/// client, in the order of preference of the server.
+#[cfg(feature = "handshake")]
+pub(crate) struct SubprotocolCallback<'a> {
+    pub(crate) supported: &'a [&'a str],
+    /// Whether to reject the clients offering none of the supported subprotocols.
+    pub(crate) required: bool,
+}
+
+#[cfg(feature = "handshake")]
+impl Callback for SubprotocolCallback<'_> {
+    fn on_request(
+        self,
+        request: &server::Request,
+        mut response: server::Response,
+    ) -> Result<server::Response, server::ErrorResponse> {
+        let offered: Vec<&str> = request
+            .headers()
+            .get_all("Sec-WebSocket-Protocol")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        let selected = self.supported.iter().find(|protocol| offered.contains(protocol));
+        match selected.and_then(|protocol| protocol.parse().ok()) {
+            Some(protocol) => {
+                response.headers_mut().insert("Sec-WebSocket-Protocol", protocol);
+                Ok(response)
+            }
+            None if self.required => {
+                debug!("None of the subprotocols offered by the client is supported");
+                let mut response = server::ErrorResponse::new(Some(
+                    "None of the offered subprotocols is supported".to_owned(),
+                ));
+                *response.status_mut() = StatusCode::BAD_REQUEST;
+                Err(response)
+            }
+            None => Ok(response),
+        }
+    }
+}
+
+/// Get the subprotocol selected by the server.
+#[cfg(feature = "handshake")]
+pub(crate) fn subprotocol(headers: &HeaderMap) -> Option<String> {
+    let protocol = headers.get("Sec-WebSocket-Protocol")?.to_str().ok()?.trim();
+    Some(protocol.to_owned()).filter(|protocol| !protocol.is_empty())
+}
+
 #[cfg(feature = "handshake")]
 struct MidHandshake<Role: HandshakeRole>(Option<WsHandshake<Role>>);
 
@@ -0,0 +1,290 @@
+//! Keeping track of the liveness of the connection: keepalive pings, idle, read, write
+//! and close timeouts.
+use std::{future::Future, pin::Pin, task::Context, time::Duration};
+
+use tokio::time::{sleep_until, Instant, Sleep};
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+/// The configuration of the automatic keepalive of a `WebSocketStream`.
+///
+/// When nothing has been received for `interval`, a ping is sent. If nothing (neither
+/// the pong nor any other message) is received within `timeout` after that, the
+/// connection is considered dead.
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAlive {
+    /// How long the connection may be idle before a ping is sent.
+    pub interval: Duration,
+    /// How long to wait for the peer after sending the ping.
+    pub timeout: Duration,
+}
+
+/// What the stream should do to keep the connection alive.
+pub(crate) enum KeepAliveAction {
+    /// Nothing to do right now.
+    Wait,
+    /// Send a ping.
+    Ping,
+    /// Flush the ping that has not been written completely yet.
+    Flush,
+    /// The peer did not answer in time.
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub(crate) struct KeepAliveState {
+    config: KeepAlive,
+    last_received: Instant,
+    ping_sent: Option<Instant>,
+    ping_unflushed: bool,
+    timer: Timer,
+}
+
+impl KeepAliveState {
+    pub(crate) fn new(config: KeepAlive) -> Self {
+        KeepAliveState {
+            config,
+            last_received: Instant::now(),
+            ping_sent: None,
+            ping_unflushed: false,
+            timer: Timer::default(),
+        }
+    }
+
+    /// Something has been received from the peer, the connection is alive.
+    pub(crate) fn received(&mut self) {
+        self.last_received = Instant::now();
+        self.ping_sent = None;
+    }
+
+    /// Record whether the ping has been flushed.
+    pub(crate) fn set_ping_unflushed(&mut self, unflushed: bool) {
+        self.ping_unflushed = unflushed;
+    }
+
+    /// Decide what to do now and make sure the task is woken up when there is
+    /// something to do next. No ping is sent unless `can_ping` is set.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>, can_ping: bool) -> KeepAliveAction {
+        let now = Instant::now();
+        let (action, deadline) = match self.ping_sent {
+            Some(sent) if now >= sent + self.config.timeout => return KeepAliveAction::TimedOut,
+            Some(sent) if self.ping_unflushed => {
+                (KeepAliveAction::Flush, sent + self.config.timeout)
+            }
+            Some(sent) => (KeepAliveAction::Wait, sent + self.config.timeout),
+            None if now >= self.last_received + self.config.interval => {
+                if can_ping {
+                    self.ping_sent = Some(now);
+                    (KeepAliveAction::Ping, now + self.config.timeout)
+                } else {
+                    // The user is in the middle of sending, check again a bit later.
+                    (KeepAliveAction::Wait, now + self.config.interval.min(Duration::from_secs(1)))
+                }
+            }
+            None => (KeepAliveAction::Wait, self.last_received + self.config.interval),
+        };
+
+        self.timer.wake_at(cx, deadline);
+        action
+    }
+}
+
+/// The configuration of the idle timeout of a `WebSocketStream`.
+///
+/// When nothing has been received or sent for `timeout`, the stream starts the closing
+/// handshake with `close_code`. If the handshake does not complete within `grace`, the
+/// connection is abandoned.
+#[derive(Debug, Clone, Copy)]
+pub struct IdleTimeout {
+    /// How long the connection may be idle before it is closed.
+    pub timeout: Duration,
+    /// The code of the close frame sent to the peer.
+    pub close_code: CloseCode,
+    /// How long to wait for the closing handshake to complete.
+    pub grace: Duration,
+}
+
+impl IdleTimeout {
+    /// An idle timeout closing the connection with `CloseCode::Normal` (1000) and
+    /// granting the closing handshake as much time as the `timeout` itself.
+    pub fn new(timeout: Duration) -> Self {
+        IdleTimeout { timeout, close_code: CloseCode::Normal, grace: timeout }
+    }
+}
+
+/// What the stream should do about an idle connection.
+pub(crate) enum IdleAction {
+    /// Nothing to do right now.
+    Wait,
+    /// Start the closing handshake.
+    Close(CloseFrame<'static>),
+    /// The closing handshake did not complete in time, give up on the connection.
+    Abandon,
+}
+
+#[derive(Debug)]
+pub(crate) struct IdleState {
+    config: IdleTimeout,
+    last_activity: Instant,
+    closing_since: Option<Instant>,
+    timer: Timer,
+}
+
+impl IdleState {
+    pub(crate) fn new(config: IdleTimeout) -> Self {
+        IdleState {
+            config,
+            last_activity: Instant::now(),
+            closing_since: None,
+            timer: Timer::default(),
+        }
+    }
+
+    /// A message has been received or sent.
+    pub(crate) fn activity(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// Decide what to do now and make sure the task is woken up when there is
+    /// something to do next.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> IdleAction {
+        let now = Instant::now();
+        let (action, deadline) = match self.closing_since {
+            Some(since) if now >= since + self.config.grace => return IdleAction::Abandon,
+            Some(since) => (IdleAction::Wait, since + self.config.grace),
+            None if now >= self.last_activity + self.config.timeout => {
+                self.closing_since = Some(now);
+                let frame = CloseFrame { code: self.config.close_code, reason: "".into() };
+                (IdleAction::Close(frame), now + self.config.grace)
+            }
+            None => (IdleAction::Wait, self.last_activity + self.config.timeout),
+        };
+
+        self.timer.wake_at(cx, deadline);
+        action
+    }
+}
+
+/// Detects writes that make no progress, i.e. a peer that stopped reading.
+#[derive(Debug)]
+pub(crate) struct WriteTimeoutState {
+    timeout: Duration,
+    /// Since when the flush has been pending, and how many bytes had been written then.
+    stalled: Option<(Instant, u64)>,
+    timer: Timer,
+}
+
+impl WriteTimeoutState {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        WriteTimeoutState { timeout, stalled: None, timer: Timer::default() }
+    }
+
+    /// The flush is pending, `written` bytes having been written so far. Returns whether
+    /// no progress has been made for too long, otherwise makes sure the task is woken
+    /// up when that would be the case.
+    pub(crate) fn pending(&mut self, cx: &mut Context<'_>, written: u64) -> bool {
+        let now = Instant::now();
+        let since = match self.stalled {
+            Some((since, at)) if at == written => since,
+            _ => {
+                self.stalled = Some((now, written));
+                now
+            }
+        };
+        if now >= since + self.timeout {
+            return true;
+        }
+        self.timer.wake_at(cx, since + self.timeout);
+        false
+    }
+
+    /// The flush has completed.
+    pub(crate) fn flushed(&mut self) {
+        self.stalled = None;
+    }
+}
+
+/// Reports reads waiting for too long, without giving up on the connection.
+#[derive(Debug)]
+pub(crate) struct ReadTimeoutState {
+    timeout: Duration,
+    /// Since when the stream has been waiting for a message.
+    since: Option<Instant>,
+    timer: Timer,
+}
+
+impl ReadTimeoutState {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        ReadTimeoutState { timeout, since: None, timer: Timer::default() }
+    }
+
+    /// No message is available yet. Returns whether the read has been waiting for too
+    /// long, in which case the wait starts over, otherwise makes sure the task is woken
+    /// up when that would be the case.
+    pub(crate) fn pending(&mut self, cx: &mut Context<'_>) -> bool {
+        let now = Instant::now();
+        let since = *self.since.get_or_insert(now);
+        if now >= since + self.timeout {
+            self.since = None;
+            return true;
+        }
+        self.timer.wake_at(cx, since + self.timeout);
+        false
+    }
+
+    /// A message, or an error, has been yielded.
+    pub(crate) fn done(&mut self) {
+        self.since = None;
+    }
+}
+
+/// Bounds the duration of the closing handshake.
+#[derive(Debug)]
+pub(crate) struct CloseTimeoutState {
+    timeout: Duration,
+    /// Since when the closing handshake has been in progress.
+    since: Option<Instant>,
+    timer: Timer,
+}
+
+impl CloseTimeoutState {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        CloseTimeoutState { timeout, since: None, timer: Timer::default() }
+    }
+
+    /// A close frame has been sent or received.
+    pub(crate) fn started(&mut self) {
+        self.since.get_or_insert_with(Instant::now);
+    }
+
+    /// Returns whether the closing handshake has been going on for too long, otherwise
+    /// makes sure the task is woken up when that would be the case.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> bool {
+        let deadline = match self.since {
+            Some(since) => since + self.timeout,
+            None => return false,
+        };
+        if Instant::now() >= deadline {
+            return true;
+        }
+        self.timer.wake_at(cx, deadline);
+        false
+    }
+}
+
+/// A timer waking up the task polling the stream at a given deadline.
+#[derive(Debug, Default)]
+pub(crate) struct Timer {
+    // Created lazily, so that the stream can be configured outside of the runtime.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Timer {
+    /// Make sure the task is woken up at `deadline`.
+    pub(crate) fn wake_at(&mut self, cx: &mut Context<'_>, deadline: Instant) {
+        let sleep = self.sleep.get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+        sleep.as_mut().reset(deadline);
+        if sleep.as_mut().poll(cx).is_ready() {
+            cx.waker().wake_by_ref();
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Sending a PROXY protocol header, for load balancers that expect one.
+use std::net::{IpAddr, SocketAddr};
+
+/// The version of the PROXY protocol header to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocolVersion {
+    /// The human-readable header of version 1.
+    V1,
+    /// The binary header of version 2.
+    V2,
+}
+
+/// The PROXY protocol header sent right after connecting and before the handshake,
+/// so that the server learns the addresses of the original connection.
+///
+/// The addresses that are not set are those of the TCP connection: `source` is its
+/// local address and `destination` the address of the peer (of the HTTP proxy, if the
+/// connection is tunneled through one).
+#[derive(Debug, Clone, Copy)]
+pub struct ProxyHeader {
+    /// The version of the header.
+    pub version: ProxyProtocolVersion,
+    /// The address of the client.
+    pub source: Option<SocketAddr>,
+    /// The address the client connected to.
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+    /// A header of `version` with the addresses of the TCP connection.
+    pub fn new(version: ProxyProtocolVersion) -> Self {
+        ProxyHeader { version, source: None, destination: None }
+    }
+
+    /// Encode the header, `local` and `peer` being the addresses of the TCP connection.
+    pub(crate) fn encode(&self, local: SocketAddr, peer: SocketAddr) -> Vec<u8> {
+        let (source, destination) =
+            same_family(self.source.unwrap_or(local), self.destination.unwrap_or(peer));
+        match self.version {
+            ProxyProtocolVersion::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {family} {} {} {} {}\r\n",
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes()
+            }
+            ProxyProtocolVersion::V2 => {
+                let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+                // Version 2, PROXY command.
+                header.push(0x21);
+                match (source.ip(), destination.ip()) {
+                    (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                        // TCP over IPv4.
+                        header.extend_from_slice(&[0x11, 0, 12]);
+                        header.extend_from_slice(&source.octets());
+                        header.extend_from_slice(&destination.octets());
+                    }
+                    (source, destination) => {
+                        // TCP over IPv6.
+                        header.extend_from_slice(&[0x21, 0, 36]);
+                        header.extend_from_slice(&ipv6(source).octets());
+                        header.extend_from_slice(&ipv6(destination).octets());
+                    }
+                }
+                header.extend_from_slice(&source.port().to_be_bytes());
+                header.extend_from_slice(&destination.port().to_be_bytes());
+                header
+            }
+        }
+    }
+}
+
+/// Map IPv4 addresses to IPv6 if the other address is an IPv6 one, the header cannot
+/// mix both families.
+fn same_family(source: SocketAddr, destination: SocketAddr) -> (SocketAddr, SocketAddr) {
+    if source.is_ipv4() == destination.is_ipv4() {
+        return (source, destination);
+    }
+    let mapped = |addr: SocketAddr| SocketAddr::new(IpAddr::V6(ipv6(addr.ip())), addr.port());
+    (mapped(source), mapped(destination))
+}
+
+fn ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProxyHeader, ProxyProtocolVersion};
+
+    #[test]
+    fn encode_headers() {
+        let local = "192.0.2.1:50000".parse().unwrap();
+        let peer = "198.51.100.2:443".parse().unwrap();
+
+        let header = ProxyHeader::new(ProxyProtocolVersion::V1).encode(local, peer);
+        assert_eq!(header, b"PROXY TCP4 192.0.2.1 198.51.100.2 50000 443\r\n");
+
+        let header = ProxyHeader {
+            source: Some("[2001:db8::1]:4000".parse().unwrap()),
+            ..ProxyHeader::new(ProxyProtocolVersion::V1)
+        };
+        assert_eq!(
+            header.encode(local, peer),
+            b"PROXY TCP6 2001:db8::1 ::ffff:198.51.100.2 4000 443\r\n"
+        );
+
+        let header = ProxyHeader::new(ProxyProtocolVersion::V2).encode(local, peer);
+        assert_eq!(&header[..12], b"\r\n\r\n\0\r\nQUIT\n");
+        assert_eq!(&header[12..16], [0x21, 0x11, 0, 12]);
+        assert_eq!(&header[16..], [192, 0, 2, 1, 198, 51, 100, 2, 0xc3, 0x50, 0x01, 0xbb]);
+    }
+}
@@ -0,0 +1,315 @@
+//! A WebSocket client connection which is established again whenever it is lost.
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::{ready, Sink, Stream};
+use tokio::{
+    net::TcpStream,
+    time::{sleep, Sleep},
+};
+use tungstenite::{
+    error::Error,
+    handshake::client::Response,
+    http::{HeaderMap, Uri},
+    protocol::WebSocketConfig,
+    Message,
+};
+
+use crate::{
+    connect::connect_async_with_config,
+    retry::{new_request, Backoff},
+    stream::MaybeTlsStream,
+    IntoClientRequest, WebSocketStream,
+};
+
+type Connection = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type Connecting = Pin<Box<dyn Future<Output = Result<(Connection, Response), Error>> + Send>>;
+
+/// What a `ReconnectingStream` does with the messages sent while it is disconnected.
+#[derive(Debug, Clone, Copy)]
+pub enum WhileDisconnected {
+    /// Buffer up to that many messages and send them once connected again. When the
+    /// buffer is full, the sink is not ready until the connection is back.
+    Buffer(usize),
+    /// Fail with an `Error::Io` of kind `std::io::ErrorKind::NotConnected`.
+    Reject,
+}
+
+/// The configuration of a `ReconnectingStream`.
+#[derive(Debug, Clone, Copy)]
+pub struct Reconnect {
+    /// The delays between the attempts to connect again.
+    pub backoff: Backoff,
+    /// What to do with the messages sent while disconnected.
+    pub while_disconnected: WhileDisconnected,
+}
+
+/// An item of a `ReconnectingStream`.
+#[derive(Debug)]
+pub enum ReconnectEvent {
+    /// A message from the server.
+    Message(Message),
+    /// The connection has been lost and established again, e.g. to subscribe again. This
+    /// is the response of the server to the new handshake.
+    Reconnected(Response),
+}
+
+enum State {
+    Connected(Connection),
+    Waiting(Pin<Box<Sleep>>),
+    Connecting(Connecting),
+    Closed,
+}
+
+/// A WebSocket client connection which is established again whenever it is lost.
+///
+/// When the connection fails or is closed by the server, the same request is sent again
+/// after the delay given by the `Backoff`, until it succeeds. The stream of messages
+/// then goes on after a `ReconnectEvent::Reconnected`. Once the sink has been closed,
+/// the connection is not established again and the stream ends.
+pub struct ReconnectingStream {
+    uri: Uri,
+    headers: HeaderMap,
+    config: Option<WebSocketConfig>,
+    reconnect: Reconnect,
+    state: State,
+    /// The number of failed attempts to connect since the connection has been lost.
+    attempt: u32,
+    /// The response to the last handshake, until it is yielded by the stream.
+    reconnected: Option<Response>,
+    buffer: VecDeque<Message>,
+    /// Whether the sink is being closed, i.e. the connection must not be established again.
+    closing: bool,
+    // The stream and the sink may be polled by different tasks, both must be woken up
+    // once connected again.
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl ReconnectingStream {
+    /// Connect to the given URL. Unlike the following ones, this first attempt is not
+    /// retried: its failure is returned.
+    pub async fn connect<R>(
+        request: R,
+        config: Option<WebSocketConfig>,
+        reconnect: Reconnect,
+    ) -> Result<(Self, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        let request = request.into_client_request()?;
+        let (uri, headers) = (request.uri().clone(), request.headers().clone());
+        let (connection, response) = connect_async_with_config(request, config, false).await?;
+        let stream = ReconnectingStream {
+            uri,
+            headers,
+            config,
+            reconnect,
+            state: State::Connected(connection),
+            attempt: 0,
+            reconnected: None,
+            buffer: VecDeque::new(),
+            closing: false,
+            read_waker: None,
+            write_waker: None,
+        };
+        Ok((stream, response))
+    }
+
+    /// Whether the connection is currently established.
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, State::Connected(_))
+    }
+
+    /// The connection has been lost (or could not be established), try again later.
+    fn disconnected(&mut self) {
+        if self.closing {
+            self.state = State::Closed;
+            return;
+        }
+        let delay = self.reconnect.backoff.delay(self.attempt);
+        self.attempt = self.attempt.saturating_add(1);
+        log::debug!("Connecting again in {delay:?}");
+        self.state = State::Waiting(Box::pin(sleep(delay)));
+    }
+
+    /// Drive the attempts to connect until the connection is established or closed.
+    fn poll_connected(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            match &mut self.state {
+                State::Connected(_) | State::Closed => return Poll::Ready(()),
+                State::Waiting(delay) => {
+                    ready!(delay.as_mut().poll(cx));
+                    let connecting = connect_async_with_config(
+                        new_request(&self.uri, &self.headers),
+                        self.config,
+                        false,
+                    );
+                    self.state = State::Connecting(Box::pin(connecting));
+                }
+                State::Connecting(connecting) => match ready!(connecting.as_mut().poll(cx)) {
+                    Ok((connection, response)) => {
+                        log::debug!("Connected again");
+                        self.state = State::Connected(connection);
+                        self.attempt = 0;
+                        self.reconnected = Some(response);
+                        if let Some(waker) = self.read_waker.take() {
+                            waker.wake();
+                        }
+                        if let Some(waker) = self.write_waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    Err(err) => {
+                        log::debug!("Failed to connect again: {err}");
+                        self.disconnected();
+                    }
+                },
+            }
+        }
+    }
+
+    /// Wait until the connection is ready to take a message, sending the buffered ones
+    /// first. Returns immediately if not connected.
+    fn poll_connection_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let connection = match &mut self.state {
+            State::Connected(connection) => connection,
+            _ => return Poll::Ready(Ok(())),
+        };
+        loop {
+            ready!(Pin::new(&mut *connection).poll_ready(cx))?;
+            match self.buffer.pop_front() {
+                Some(message) => Pin::new(&mut *connection).start_send(message)?,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+fn not_connected() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::NotConnected, "the connection is being re-established"))
+}
+
+impl Stream for ReconnectingStream {
+    type Item = Result<ReconnectEvent, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(response) = this.reconnected.take() {
+                return Poll::Ready(Some(Ok(ReconnectEvent::Reconnected(response))));
+            }
+            if this.poll_connected(cx).is_pending() {
+                this.read_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let connection = match &mut this.state {
+                State::Connected(connection) => connection,
+                _ => return Poll::Ready(None),
+            };
+            match ready!(Pin::new(connection).poll_next(cx)) {
+                // The close frame is answered automatically, the stream ends right after.
+                Some(Ok(Message::Close(_))) => {}
+                Some(Ok(message)) => {
+                    return Poll::Ready(Some(Ok(ReconnectEvent::Message(message))));
+                }
+                Some(Err(err)) => {
+                    log::debug!("Connection lost: {err}");
+                    this.disconnected();
+                }
+                None => {
+                    log::debug!("Connection closed");
+                    this.disconnected();
+                }
+            }
+        }
+    }
+}
+
+impl Sink<Message> for ReconnectingStream {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        loop {
+            if this.poll_connected(cx).is_pending() {
+                return match this.reconnect.while_disconnected {
+                    WhileDisconnected::Buffer(capacity) if this.buffer.len() < capacity => {
+                        Poll::Ready(Ok(()))
+                    }
+                    WhileDisconnected::Buffer(_) => {
+                        this.write_waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                    WhileDisconnected::Reject => Poll::Ready(Err(not_connected())),
+                };
+            }
+            if let State::Closed = this.state {
+                return Poll::Ready(Err(Error::AlreadyClosed));
+            }
+            match ready!(this.poll_connection_ready(cx)) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(err) => {
+                    log::debug!("Connection lost: {err}");
+                    this.disconnected();
+                }
+            }
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        match &mut this.state {
+            State::Connected(connection) => {
+                let result = Pin::new(connection).start_send(item);
+                if let Err(err) = &result {
+                    log::debug!("Connection lost: {err}");
+                    this.disconnected();
+                }
+                result
+            }
+            State::Closed => Err(Error::AlreadyClosed),
+            _ => match this.reconnect.while_disconnected {
+                WhileDisconnected::Buffer(_) => {
+                    this.buffer.push_back(item);
+                    Ok(())
+                }
+                WhileDisconnected::Reject => Err(not_connected()),
+            },
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        // While disconnected, the buffered messages are sent once connected again.
+        let result = match ready!(this.poll_connection_ready(cx)) {
+            Ok(()) => match &mut this.state {
+                State::Connected(connection) => ready!(Pin::new(connection).poll_flush(cx)),
+                _ => Ok(()),
+            },
+            Err(err) => Err(err),
+        };
+        if let Err(err) = &result {
+            log::debug!("Connection lost: {err}");
+            this.disconnected();
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.closing = true;
+        let result = match &mut this.state {
+            State::Connected(connection) => ready!(Pin::new(connection).poll_close(cx)),
+            _ => Ok(()),
+        };
+        this.state = State::Closed;
+        this.buffer.clear();
+        Poll::Ready(result)
+    }
+}
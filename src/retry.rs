@@ -0,0 +1,79 @@
+//! Attempting to connect again: the delays between the attempts, and the requests for
+//! them.
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use tungstenite::{
+    error::{Error, ProtocolError},
+    handshake::client::{generate_key, Request},
+    http::{HeaderMap, Uri},
+};
+
+/// Exponential backoff between two attempts to connect.
+///
+/// The attempt number `n` (starting from 0) is delayed by `base * 2^n`, but never by
+/// more than `max`. With a `jitter` of e.g. `0.5`, up to half of the delay is randomly
+/// cut off, so that many clients losing their connection at once do not all come back
+/// at the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// The delay before the first attempt.
+    pub base: Duration,
+    /// The maximum delay before an attempt.
+    pub max: Duration,
+    /// The fraction of the delay that is randomized, between `0.0` and `1.0`.
+    pub jitter: f64,
+}
+
+impl Backoff {
+    /// The delay before the attempt number `attempt`.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base.saturating_mul(2u32.saturating_pow(attempt)).min(self.max);
+        let jitter = if self.jitter > 0.0 { self.jitter.min(1.0) * random() } else { 0.0 };
+        delay.mul_f64(1.0 - jitter)
+    }
+}
+
+/// How `connect_async_with_retry()` attempts to connect again after a failure.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// The delays between the attempts.
+    pub backoff: Backoff,
+}
+
+/// Whether connecting again may succeed after failing with `err`.
+///
+/// I/O and TLS errors, incomplete handshakes and `5xx` responses are transient, while
+/// invalid URLs, `4xx` responses and protocol violations are not.
+pub(crate) fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Io(_) => true,
+        #[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+        Error::Tls(_) => true,
+        Error::Protocol(ProtocolError::HandshakeIncomplete) => true,
+        Error::Http(response) => response.status().is_server_error(),
+        _ => false,
+    }
+}
+
+/// A copy of a request for a new handshake, with a new key.
+pub(crate) fn new_request(uri: &Uri, headers: &HeaderMap) -> Request {
+    let mut request = Request::new(());
+    *request.uri_mut() = uri.clone();
+    *request.headers_mut() = headers.clone();
+    if let Ok(key) = generate_key().parse() {
+        request.headers_mut().insert("Sec-WebSocket-Key", key);
+    }
+    request
+}
+
+/// A random number between 0 and 1, good enough for a jitter.
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,50 @@
+//! Connecting through a `tower::Service`.
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::net::TcpStream;
+use tower_service::Service;
+use tungstenite::{
+    error::Error,
+    handshake::client::{Request, Response},
+    protocol::WebSocketConfig,
+};
+
+use crate::{connect_async_with_config, stream::MaybeTlsStream, WebSocketStream};
+
+/// A `tower::Service` connecting to the requested URL, for composing the connection
+/// with tower middleware such as timeouts, retries or rate limits.
+///
+/// Calling the service is the same as calling `connect_async_with_config()` with the
+/// given request. The service is always ready.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectService {
+    config: Option<WebSocketConfig>,
+    disable_nagle: bool,
+}
+
+impl ConnectService {
+    /// A service connecting with the given `config`, disabling Nagle's algorithm on the
+    /// TCP socket if `disable_nagle` is set.
+    pub fn new(config: Option<WebSocketConfig>, disable_nagle: bool) -> Self {
+        ConnectService { config, disable_nagle }
+    }
+}
+
+impl Service<Request> for ConnectService {
+    type Response = (WebSocketStream<MaybeTlsStream<TcpStream>>, Response);
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let ConnectService { config, disable_nagle } = *self;
+        Box::pin(connect_async_with_config(request, config, disable_nagle))
+    }
+}
@@ -0,0 +1,72 @@
+//! Sending through a `WebSocketStream` from several tasks.
+use std::future::Future;
+
+use futures_util::{
+    sink::SinkExt,
+    stream::{SplitSink, SplitStream, StreamExt},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, oneshot},
+};
+use tungstenite::{error::Error, Message};
+
+use crate::WebSocketStream;
+
+type Queued = (Message, oneshot::Sender<Result<(), Error>>);
+
+/// A handle to send messages through a `WebSocketStream` split with `split_shared()`.
+///
+/// It can be cloned to send from several tasks, the messages are sent one after another
+/// by the writer returned along with it.
+#[derive(Debug, Clone)]
+pub struct MessageSender {
+    queue: mpsc::Sender<Queued>,
+}
+
+impl MessageSender {
+    /// Send a message and wait until it has been written and flushed.
+    ///
+    /// This waits for room in the queue first, so that producers cannot get ahead of the
+    /// connection. The error of sending this very message is returned as is, once the
+    /// writer has stopped because of an error (or has been dropped), `Error::AlreadyClosed`
+    /// is returned.
+    pub async fn send(&self, message: Message) -> Result<(), Error> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.queue.send((message, result_tx)).await.map_err(|_| Error::AlreadyClosed)?;
+        result_rx.await.unwrap_or(Err(Error::AlreadyClosed))
+    }
+}
+
+pub(crate) fn split_shared<S>(
+    stream: WebSocketStream<S>,
+    capacity: usize,
+) -> (MessageSender, SplitStream<WebSocketStream<S>>, impl Future<Output = ()>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (sink, stream) = stream.split();
+    let (queue, queued) = mpsc::channel(capacity);
+    (MessageSender { queue }, stream, write(sink, queued))
+}
+
+/// Send the queued messages until an error occurs or all the senders are dropped, in
+/// which case the connection is closed.
+async fn write<S>(
+    mut sink: SplitSink<WebSocketStream<S>, Message>,
+    mut queued: mpsc::Receiver<Queued>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some((message, result_tx)) = queued.recv().await {
+        let result = sink.send(message).await;
+        let failed = result.is_err();
+        let _ = result_tx.send(result);
+        if failed {
+            return;
+        }
+    }
+    if let Err(err) = sink.close().await {
+        log::debug!("Failed to close the connection: {err}");
+    }
+}
@@ -0,0 +1,83 @@
+//! Counting what goes through a `WebSocketStream`.
+use std::time::Instant;
+
+use tungstenite::protocol::Message;
+
+/// Statistics about the messages sent and received by a `WebSocketStream`, see
+/// `WebSocketStream::with_stats()`.
+///
+/// Every message counts, including the pings, pongs and close frames passed to the
+/// `Sink` or yielded by the `Stream`. Bytes are those of the payloads of the messages,
+/// the framing is not accounted for. The pongs sent automatically in reply to pings
+/// are not counted, but the pings sent by the keepalive are.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default)]
+pub struct StreamStats {
+    /// The number of messages sent.
+    pub messages_sent: u64,
+    /// The number of messages received.
+    pub messages_received: u64,
+    /// The size of the payloads of the messages sent.
+    pub bytes_sent: u64,
+    /// The size of the payloads of the messages received.
+    pub bytes_received: u64,
+    /// The number of pings sent.
+    pub pings_sent: u64,
+    /// The number of pings received.
+    pub pings_received: u64,
+    /// The number of pongs sent.
+    pub pongs_sent: u64,
+    /// The number of pongs received.
+    pub pongs_received: u64,
+    /// When a message was last sent.
+    pub last_sent: Option<Instant>,
+    /// When a message was last received.
+    pub last_received: Option<Instant>,
+}
+
+impl StreamStats {
+    /// Count a message passed to the `Sink`, given its kind and length as it is about
+    /// to be consumed.
+    pub(crate) fn sent(&mut self, kind: MessageKind, len: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent += len as u64;
+        match kind {
+            MessageKind::Ping => self.pings_sent += 1,
+            MessageKind::Pong => self.pongs_sent += 1,
+            MessageKind::Close | MessageKind::Other => {}
+        }
+        self.last_sent = Some(Instant::now());
+    }
+
+    /// Count a message yielded by the `Stream`.
+    pub(crate) fn received(&mut self, message: &Message) {
+        self.messages_received += 1;
+        self.bytes_received += message.len() as u64;
+        match MessageKind::of(message) {
+            MessageKind::Ping => self.pings_received += 1,
+            MessageKind::Pong => self.pongs_received += 1,
+            MessageKind::Close | MessageKind::Other => {}
+        }
+        self.last_received = Some(Instant::now());
+    }
+}
+
+/// The kind of a message, as far as the statistics are concerned.
+#[derive(Clone, Copy)]
+pub(crate) enum MessageKind {
+    Ping,
+    Pong,
+    Close,
+    Other,
+}
+
+impl MessageKind {
+    pub(crate) fn of(message: &Message) -> Self {
+        match message {
+            Message::Ping(_) => MessageKind::Ping,
+            Message::Pong(_) => MessageKind::Pong,
+            Message::Close(_) => MessageKind::Close,
+            _ => MessageKind::Other,
+        }
+    }
+}
@@ -24,6 +24,88 @@ pub enum MaybeTlsStream<S> {
     Rustls(tokio_rustls::client::TlsStream<S>),
 }
 
+impl<S> MaybeTlsStream<S> {
+    /// Returns a reference to the underlying stream, i.e. the one TLS runs over.
+    pub fn get_ref(&self) -> &S {
+        match self {
+            MaybeTlsStream::Plain(s) => s,
+            #[cfg(feature = "native-tls")]
+            MaybeTlsStream::NativeTls(s) => s.get_ref().get_ref().get_ref(),
+            #[cfg(feature = "__rustls-tls")]
+            MaybeTlsStream::Rustls(s) => s.get_ref().0,
+        }
+    }
+
+    /// Returns whether the stream is encrypted, i.e. it is not `Plain`.
+    pub fn is_tls(&self) -> bool {
+        !matches!(self, MaybeTlsStream::Plain(_))
+    }
+
+    /// Returns the version of the TLS protocol negotiated with the server, e.g. `TLSv1.3`.
+    ///
+    /// This is `None` for `Plain` streams, as well as with `native-tls`, which does not
+    /// expose it.
+    pub fn tls_version(&self) -> Option<String> {
+        match self {
+            MaybeTlsStream::Plain(_) => None,
+            #[cfg(feature = "native-tls")]
+            MaybeTlsStream::NativeTls(_) => None,
+            #[cfg(feature = "__rustls-tls")]
+            MaybeTlsStream::Rustls(s) => {
+                use rustls::ProtocolVersion;
+                s.get_ref().1.protocol_version().map(|version| match version {
+                    ProtocolVersion::TLSv1_2 => "TLSv1.2".to_owned(),
+                    ProtocolVersion::TLSv1_3 => "TLSv1.3".to_owned(),
+                    version => format!("{version:?}"),
+                })
+            }
+        }
+    }
+
+    /// Returns the cipher suite negotiated with the server, e.g.
+    /// `TLS13_AES_256_GCM_SHA384`.
+    ///
+    /// This is `None` for `Plain` streams, as well as with `native-tls`, which does not
+    /// expose it.
+    pub fn cipher_suite(&self) -> Option<String> {
+        match self {
+            MaybeTlsStream::Plain(_) => None,
+            #[cfg(feature = "native-tls")]
+            MaybeTlsStream::NativeTls(_) => None,
+            #[cfg(feature = "__rustls-tls")]
+            MaybeTlsStream::Rustls(s) => {
+                s.get_ref().1.negotiated_cipher_suite().map(|suite| format!("{:?}", suite.suite()))
+            }
+        }
+    }
+
+    /// Returns the protocol selected by the server through ALPN during the TLS handshake.
+    ///
+    /// This is `None` if no protocol was negotiated, in particular for `Plain` streams.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        match self {
+            MaybeTlsStream::Plain(_) => None,
+            #[cfg(feature = "native-tls")]
+            MaybeTlsStream::NativeTls(s) => s.get_ref().negotiated_alpn().ok().flatten(),
+            #[cfg(feature = "__rustls-tls")]
+            MaybeTlsStream::Rustls(s) => s.get_ref().1.alpn_protocol().map(|p| p.to_vec()),
+        }
+    }
+}
+
+#[cfg(feature = "connect")]
+impl MaybeTlsStream<tokio::net::TcpStream> {
+    /// Returns the socket address of the remote peer of the underlying TCP connection.
+    pub fn peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.get_ref().peer_addr()
+    }
+
+    /// Returns the local socket address of the underlying TCP connection.
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.get_ref().local_addr()
+    }
+}
+
 impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for MaybeTlsStream<S> {
     fn poll_read(
         self: Pin<&mut Self>,
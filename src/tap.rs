@@ -0,0 +1,107 @@
+//! Observing the raw bytes going through a stream.
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Which way the bytes observed by a `WireTap` go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapDirection {
+    /// Read from the stream.
+    Inbound,
+    /// Written to the stream.
+    Outbound,
+}
+
+/// A stream passing the bytes read from and written to the stream it wraps to a
+/// callback, e.g. to debug the handshake or the framing.
+///
+/// The bytes are only observed, never altered. To observe the plain bytes of a TLS
+/// connection, wrap the TLS stream (e.g. a `MaybeTlsStream`) and pass the `WireTap` to
+/// `client_async()` or `accept_async()`: wrapping the TCP stream instead shows the
+/// encrypted bytes.
+#[derive(Debug)]
+pub struct WireTap<S, F> {
+    inner: S,
+    tap: F,
+}
+
+impl<S, F> WireTap<S, F>
+where
+    F: FnMut(TapDirection, &[u8]),
+{
+    /// Wrap `inner`, calling `tap` with every chunk of bytes read or written.
+    pub fn new(inner: S, tap: F) -> Self {
+        WireTap { inner, tap }
+    }
+
+    /// Returns a shared reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the inner stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F> AsyncRead for WireTap<S, F>
+where
+    S: AsyncRead + Unpin,
+    F: FnMut(TapDirection, &[u8]) + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            let read = &buf.filled()[before..];
+            if !read.is_empty() {
+                (this.tap)(TapDirection::Inbound, read);
+            }
+        }
+        res
+    }
+}
+
+impl<S, F> AsyncWrite for WireTap<S, F>
+where
+    S: AsyncWrite + Unpin,
+    F: FnMut(TapDirection, &[u8]) + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = res {
+            if written > 0 {
+                (this.tap)(TapDirection::Outbound, &buf[..written]);
+            }
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
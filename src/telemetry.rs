@@ -0,0 +1,72 @@
+//! Metrics emitted through the `metrics` crate, with the `metrics` feature.
+//!
+//! - `ws_connections_opened_total`: WebSocket connections established, by clients and
+//!   servers alike.
+//! - `ws_messages_sent_total` and `ws_messages_received_total`, labeled with the `kind`
+//!   of message: `data` (text, binary and raw frames), `ping`, `pong` or `close`.
+//! - `ws_bytes_sent_total` and `ws_bytes_received_total`: the size of the payloads of
+//!   these messages, labeled the same way.
+//! - `ws_errors_total`, labeled with the `kind` of the error, e.g. `io`, `protocol` or
+//!   `capacity`: the errors of the `Stream`, the `Sink` and the connect helpers.
+//! - `ws_connect_duration_seconds`: a histogram of the duration of the phases of the
+//!   connections established by the connect helpers, labeled with the `phase`:
+//!   `tcp_connect`, `proxy_handshake`, `tls_handshake` or `websocket_handshake`.
+use metrics::{counter, histogram, increment_counter};
+use tungstenite::{error::Error, Message};
+
+use crate::stats::MessageKind;
+
+pub(crate) fn connection_opened() {
+    increment_counter!("ws_connections_opened_total");
+}
+
+pub(crate) fn message_sent(kind: MessageKind, len: usize) {
+    let kind = kind_label(kind);
+    increment_counter!("ws_messages_sent_total", "kind" => kind);
+    counter!("ws_bytes_sent_total", len as u64, "kind" => kind);
+}
+
+pub(crate) fn message_received(message: &Message) {
+    let kind = kind_label(MessageKind::of(message));
+    increment_counter!("ws_messages_received_total", "kind" => kind);
+    counter!("ws_bytes_received_total", message.len() as u64, "kind" => kind);
+}
+
+pub(crate) fn error(error: &Error) {
+    let kind = match error {
+        Error::ConnectionClosed => "connection_closed",
+        Error::AlreadyClosed => "already_closed",
+        Error::Io(_) => "io",
+        Error::Capacity(_) => "capacity",
+        Error::Protocol(_) => "protocol",
+        Error::Url(_) => "url",
+        Error::Http(_) => "http",
+        _ => "other",
+    };
+    increment_counter!("ws_errors_total", "kind" => kind);
+}
+
+#[cfg(feature = "connect")]
+pub(crate) fn connected(timings: &crate::ConnectTimings) {
+    histogram!("ws_connect_duration_seconds", timings.tcp_connect, "phase" => "tcp_connect");
+    if let Some(duration) = timings.proxy_handshake {
+        histogram!("ws_connect_duration_seconds", duration, "phase" => "proxy_handshake");
+    }
+    if let Some(duration) = timings.tls_handshake {
+        histogram!("ws_connect_duration_seconds", duration, "phase" => "tls_handshake");
+    }
+    histogram!(
+        "ws_connect_duration_seconds",
+        timings.websocket_handshake,
+        "phase" => "websocket_handshake"
+    );
+}
+
+fn kind_label(kind: MessageKind) -> &'static str {
+    match kind {
+        MessageKind::Ping => "ping",
+        MessageKind::Pong => "pong",
+        MessageKind::Close => "close",
+        MessageKind::Other => "data",
+    }
+}
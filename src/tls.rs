@@ -1,8 +1,13 @@
 //! Connection helper.
+use std::{io, time::Duration};
+
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use tungstenite::{
-    client::uri_mode, error::Error, handshake::client::Response, protocol::WebSocketConfig,
+    client::uri_mode,
+    error::Error,
+    handshake::client::{Request, Response},
+    protocol::WebSocketConfig,
 };
 
 use crate::{client_async_with_config, IntoClientRequest, WebSocketStream};
@@ -21,11 +26,99 @@ pub enum Connector {
     #[cfg(feature = "native-tls")]
     NativeTls(native_tls_crate::TlsConnector),
     /// `rustls` TLS connector.
+    ///
+    /// The configuration holds the cache of the TLS sessions to resume, so sharing the
+    /// same `Arc` between connections to the same server (rather than creating a new
+    /// configuration for each of them) lets them use TLS session resumption. Without a
+    /// connector, a default configuration shared by all connections is used.
+    ///
+    /// With the `rustls-early-data` feature, setting `enable_early_data` in the
+    /// configuration sends the handshake request as TLS 1.3 early data (0-RTT) when a
+    /// session is resumed. If the server rejects the early data, the request is sent
+    /// again once the TLS handshake has completed. Beware that early data may be
+    /// replayed by an attacker: only enable it if accepting the same handshake request
+    /// more than once is harmless to the server.
     #[cfg(feature = "__rustls-tls")]
     Rustls(std::sync::Arc<rustls::ClientConfig>),
+    /// TLS connector trusting only the servers whose leaf certificate is pinned, i.e.
+    /// whose SHA-256 fingerprint (of the DER encoding) is one of the given ones.
+    ///
+    /// The pins replace the verification against the certificate authorities and the
+    /// name of the server. A certificate that does not match is reported as
+    /// `Error::Tls`. Only available with the `rustls-pinning` feature, which checks the
+    /// pins with `rustls` whatever the backend of the other connectors.
+    #[cfg(feature = "rustls-pinning")]
+    Pinned(Vec<[u8; 32]>),
+}
+
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+impl Connector {
+    /// A TLS connector trusting the PEM-encoded certificates read from `pem` in addition
+    /// to the default roots, e.g. the certificate authority of an internal network.
+    ///
+    /// `rustls` is used if it is enabled, with the roots of the `rustls-tls-native-roots`
+    /// and `rustls-tls-webpki-roots` features, `native-tls` otherwise, with the roots of
+    /// the system. Input without any certificate, or with a certificate that cannot be
+    /// parsed, is rejected with an `Error::Io` of kind `std::io::ErrorKind::InvalidData`.
+    pub fn with_root_certificates<R: io::Read>(pem: R) -> Result<Self, Error> {
+        let certs = rustls_pemfile::certs(&mut io::BufReader::new(pem)).map_err(Error::Io)?;
+        if certs.is_empty() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no PEM-encoded certificate found",
+            )));
+        }
+        #[cfg(feature = "__rustls-tls")]
+        {
+            self::encryption::rustls::config_with_extra_roots(&certs).map(Connector::Rustls)
+        }
+        #[cfg(not(feature = "__rustls-tls"))]
+        {
+            self::encryption::native_tls::connector_with_extra_roots(&certs)
+                .map(Connector::NativeTls)
+        }
+    }
+
+    /// The same as `with_root_certificates()` but the certificates are read from the
+    /// file at `path`.
+    pub fn with_root_certificates_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, Error> {
+        Self::with_root_certificates(std::fs::File::open(path)?)
+    }
+
+    /// **Dangerous**: a TLS connector accepting any certificate, for whatever name,
+    /// e.g. to develop against a server with a self-signed certificate.
+    ///
+    /// This disables the authentication of the server altogether, anyone able to
+    /// intercept the connection can impersonate it: never use it in production. Prefer
+    /// `with_root_certificates()` or `Connector::Pinned` to trust a specific certificate.
+    /// A warning is logged whenever such a connector is created. `rustls` is used if it
+    /// is enabled, `native-tls` otherwise.
+    ///
+    /// Only available with the `danger-accept-invalid-certs` feature, so that it cannot
+    /// be used by accident.
+    #[cfg(feature = "danger-accept-invalid-certs")]
+    pub fn danger_accept_invalid_certs() -> Result<Self, Error> {
+        log::warn!("Certificate verification is disabled, the server is not authenticated");
+        #[cfg(feature = "__rustls-tls")]
+        {
+            Ok(Connector::Rustls(self::encryption::rustls::unverified_config()))
+        }
+        #[cfg(not(feature = "__rustls-tls"))]
+        {
+            self::encryption::native_tls::unverified_connector().map(Connector::NativeTls)
+        }
+    }
 }
 
 mod encryption {
+    /// Whether the fingerprint of the certificate `der` is one of the `pins`.
+    #[cfg(feature = "rustls-pinning")]
+    fn is_pinned(pins: &[[u8; 32]], der: &[u8]) -> bool {
+        use sha2::{Digest, Sha256};
+        let fingerprint: [u8; 32] = Sha256::digest(der).into();
+        pins.contains(&fingerprint)
+    }
+
     #[cfg(feature = "native-tls")]
     pub mod native_tls {
         use native_tls_crate::TlsConnector;
@@ -60,15 +153,49 @@ mod encryption {
                 }
             }
         }
+
+        /// A connector trusting the roots of the system and the DER-encoded `certs`.
+        #[cfg(not(feature = "__rustls-tls"))]
+        pub fn connector_with_extra_roots(certs: &[Vec<u8>]) -> Result<TlsConnector, Error> {
+            let mut builder = TlsConnector::builder();
+            for cert in certs {
+                let cert = native_tls_crate::Certificate::from_der(cert).map_err(|e| {
+                    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+                })?;
+                builder.add_root_certificate(cert);
+            }
+            Ok(builder.build().map_err(TlsError::Native)?)
+        }
+
+        /// A connector accepting any certificate, see `Connector::danger_accept_invalid_certs()`.
+        #[cfg(all(feature = "danger-accept-invalid-certs", not(feature = "__rustls-tls")))]
+        pub fn unverified_connector() -> Result<TlsConnector, Error> {
+            let connector = TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true)
+                .build()
+                .map_err(TlsError::Native)?;
+            Ok(connector)
+        }
     }
 
     #[cfg(feature = "__rustls-tls")]
     pub mod rustls {
+        #[cfg(any(feature = "danger-accept-invalid-certs", feature = "rustls-pinning"))]
+        use rustls::client::{ServerCertVerified, ServerCertVerifier};
+        #[cfg(feature = "rustls-pinning")]
+        use rustls::CertificateError;
         pub use rustls::ClientConfig;
-        use rustls::{RootCertStore, ServerName};
+        use rustls::{Certificate, RootCertStore, ServerName};
         use tokio_rustls::TlsConnector as TokioTlsConnector;
 
-        use std::{convert::TryFrom, sync::Arc};
+        #[cfg(any(feature = "danger-accept-invalid-certs", feature = "rustls-pinning"))]
+        use std::time::SystemTime;
+        use std::{
+            convert::TryFrom,
+            io,
+            sync::{Arc, Mutex},
+        };
         use tokio::io::{AsyncRead, AsyncWrite};
 
         use tungstenite::{error::TlsError, stream::Mode, Error};
@@ -89,42 +216,14 @@ mod encryption {
                 Mode::Tls => {
                     let config = match tls_connector {
                         Some(config) => config,
-                        None => {
-                            #[allow(unused_mut)]
-                            let mut root_store = RootCertStore::empty();
-                            #[cfg(feature = "rustls-tls-native-roots")]
-                            {
-                                let native_certs = rustls_native_certs::load_native_certs()?;
-                                let der_certs: Vec<Vec<u8>> =
-                                    native_certs.into_iter().map(|cert| cert.0).collect();
-                                let total_number = der_certs.len();
-                                let (number_added, number_ignored) =
-                                    root_store.add_parsable_certificates(&der_certs);
-                                log::debug!("Added {number_added}/{total_number} native root certificates (ignored {number_ignored})");
-                            }
-                            #[cfg(feature = "rustls-tls-webpki-roots")]
-                            {
-                                root_store.add_server_trust_anchors(
-                                    webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
-                                        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
-                                            ta.subject,
-                                            ta.spki,
-                                            ta.name_constraints,
-                                        )
-                                    })
-                                );
-                            }
-
-                            Arc::new(
-                                ClientConfig::builder()
-                                    .with_safe_defaults()
-                                    .with_root_certificates(root_store)
-                                    .with_no_client_auth(),
-                            )
-                        }
+                        None => default_config()?,
                     };
                     let domain = ServerName::try_from(domain.as_str())
                         .map_err(|_| TlsError::InvalidDnsName)?;
+                    #[cfg(feature = "rustls-early-data")]
+                    let stream = TokioTlsConnector::from(config.clone())
+                        .early_data(config.enable_early_data);
+                    #[cfg(not(feature = "rustls-early-data"))]
                     let stream = TokioTlsConnector::from(config);
                     let connected = stream.connect(domain, socket).await;
 
@@ -135,6 +234,152 @@ mod encryption {
                 }
             }
         }
+
+        /// The configuration used without a `Connector`. It is created once and shared by
+        /// all connections, so that they share the cache of the sessions to resume.
+        static DEFAULT_CONFIG: Mutex<Option<Arc<ClientConfig>>> = Mutex::new(None);
+
+        pub(crate) fn default_config() -> Result<Arc<ClientConfig>, Error> {
+            let mut default_config = DEFAULT_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(config) = &*default_config {
+                return Ok(config.clone());
+            }
+            let config = config_with_roots(default_roots()?);
+            *default_config = Some(config.clone());
+            Ok(config)
+        }
+
+        /// A configuration trusting the default roots and the DER-encoded `certs`.
+        pub fn config_with_extra_roots(certs: &[Vec<u8>]) -> Result<Arc<ClientConfig>, Error> {
+            let mut root_store = default_roots()?;
+            for cert in certs {
+                root_store.add(&Certificate(cert.clone())).map_err(|e| {
+                    Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid root certificate: {e:?}"),
+                    ))
+                })?;
+            }
+            Ok(config_with_roots(root_store))
+        }
+
+        /// The roots enabled by the `rustls-tls-native-roots` and `rustls-tls-webpki-roots`
+        /// features.
+        fn default_roots() -> Result<RootCertStore, Error> {
+            #[allow(unused_mut)]
+            let mut root_store = RootCertStore::empty();
+            #[cfg(feature = "rustls-tls-native-roots")]
+            {
+                let native_certs = rustls_native_certs::load_native_certs()?;
+                let der_certs: Vec<Vec<u8>> = native_certs.into_iter().map(|cert| cert.0).collect();
+                let total_number = der_certs.len();
+                let (number_added, number_ignored) =
+                    root_store.add_parsable_certificates(&der_certs);
+                log::debug!("Added {number_added}/{total_number} native root certificates (ignored {number_ignored})");
+            }
+            #[cfg(feature = "rustls-tls-webpki-roots")]
+            {
+                root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+                    |ta| {
+                        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                            ta.subject,
+                            ta.spki,
+                            ta.name_constraints,
+                        )
+                    },
+                ));
+            }
+            Ok(root_store)
+        }
+
+        fn config_with_roots(root_store: RootCertStore) -> Arc<ClientConfig> {
+            Arc::new(
+                ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_root_certificates(root_store)
+                    .with_no_client_auth(),
+            )
+        }
+
+        #[cfg(feature = "rustls-pinning")]
+        pub async fn wrap_stream_pinned<S>(
+            socket: S,
+            domain: String,
+            mode: Mode,
+            pins: Vec<[u8; 32]>,
+        ) -> Result<MaybeTlsStream<S>, Error>
+        where
+            S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+        {
+            let config = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(PinnedVerifier { pins }))
+                .with_no_client_auth();
+            wrap_stream(socket, domain, mode, Some(Arc::new(config))).await.map_err(|e| match e {
+                Error::Io(e) => match e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+                    Some(e) => Error::Tls(TlsError::Rustls(e.clone())),
+                    None => Error::Io(e),
+                },
+                e => e,
+            })
+        }
+
+        /// A configuration accepting any certificate, see
+        /// `Connector::danger_accept_invalid_certs()`.
+        #[cfg(feature = "danger-accept-invalid-certs")]
+        pub fn unverified_config() -> Arc<ClientConfig> {
+            let config = ClientConfig::builder()
+                .with_safe_defaults()
+                .with_custom_certificate_verifier(Arc::new(NoVerification))
+                .with_no_client_auth();
+            Arc::new(config)
+        }
+
+        /// Accepts any certificate.
+        #[cfg(feature = "danger-accept-invalid-certs")]
+        struct NoVerification;
+
+        #[cfg(feature = "danger-accept-invalid-certs")]
+        impl ServerCertVerifier for NoVerification {
+            fn verify_server_cert(
+                &self,
+                _end_entity: &Certificate,
+                _intermediates: &[Certificate],
+                _server_name: &ServerName,
+                _scts: &mut dyn Iterator<Item = &[u8]>,
+                _ocsp_response: &[u8],
+                _now: SystemTime,
+            ) -> Result<ServerCertVerified, rustls::Error> {
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+
+        /// Accepts the certificates pinned by `Connector::Pinned`.
+        #[cfg(feature = "rustls-pinning")]
+        struct PinnedVerifier {
+            pins: Vec<[u8; 32]>,
+        }
+
+        #[cfg(feature = "rustls-pinning")]
+        impl ServerCertVerifier for PinnedVerifier {
+            fn verify_server_cert(
+                &self,
+                end_entity: &Certificate,
+                _intermediates: &[Certificate],
+                _server_name: &ServerName,
+                _scts: &mut dyn Iterator<Item = &[u8]>,
+                _ocsp_response: &[u8],
+                _now: SystemTime,
+            ) -> Result<ServerCertVerified, rustls::Error> {
+                if super::is_pinned(&self.pins, &end_entity.0) {
+                    Ok(ServerCertVerified::assertion())
+                } else {
+                    Err(rustls::Error::InvalidCertificate(
+                        CertificateError::ApplicationVerificationFailure,
+                    ))
+                }
+            }
+        }
     }
 
     pub mod plain {
@@ -185,20 +430,90 @@ pub async fn client_async_tls_with_config<R, S>(
     config: Option<WebSocketConfig>,
     connector: Option<Connector>,
 ) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+    MaybeTlsStream<S>: Unpin,
+{
+    client_async_tls_with_options(request, stream, config, connector, None).await
+}
+
+/// The same as `client_async_tls_with_config()` but the WebSocket handshake is bounded
+/// by `timeout`.
+///
+/// The timeout starts once the stream is ready to send the upgrade request, i.e. after
+/// the TLS handshake if any, and ends with the response of the server. If the server
+/// does not complete the handshake in time, the stream is dropped and an `Error::Io` of
+/// kind `std::io::ErrorKind::TimedOut` is returned.
+///
+/// Please refer to `client_async_tls()` for more details.
+#[cfg(all(any(feature = "native-tls", feature = "__rustls-tls"), feature = "time"))]
+pub async fn client_async_tls_with_timeout<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+    connector: Option<Connector>,
+    timeout: Duration,
+) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+    MaybeTlsStream<S>: Unpin,
+{
+    client_async_tls_with_options(request, stream, config, connector, Some(timeout)).await
+}
+
+/// The same as `client_async_tls_with_config()` but the WebSocket handshake is bounded
+/// by `handshake_timeout`, if given.
+async fn client_async_tls_with_options<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+    connector: Option<Connector>,
+    handshake_timeout: Option<Duration>,
+) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
 where
     R: IntoClientRequest + Unpin,
     S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
     MaybeTlsStream<S>: Unpin,
 {
     let request = request.into_client_request()?;
+    let stream = wrap_stream(&request, stream, connector, None).await?;
 
+    let handshake = client_async_with_config(request, stream, config);
+    #[cfg(feature = "time")]
+    if let Some(timeout) = handshake_timeout {
+        return tokio::time::timeout(timeout, handshake).await.unwrap_or_else(|_| {
+            Err(Error::Io(io::Error::new(io::ErrorKind::TimedOut, "WebSocket handshake timed out")))
+        });
+    }
+    #[cfg(not(feature = "time"))]
+    let _ = handshake_timeout;
+    handshake.await
+}
+
+/// Upgrade `stream` to TLS if `request` is for a `wss://` URL, see `client_async_tls()`.
+pub(crate) async fn wrap_stream<S>(
+    request: &Request,
+    stream: S,
+    connector: Option<Connector>,
+    server_name: Option<String>,
+) -> Result<MaybeTlsStream<S>, Error>
+where
+    S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+{
     #[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
-    let domain = crate::domain(&request)?;
+    let domain = match server_name {
+        Some(server_name) => check_server_name(server_name)?,
+        None => crate::domain(request)?,
+    };
+    #[cfg(not(any(feature = "native-tls", feature = "__rustls-tls")))]
+    let _ = server_name;
 
     // Make sure we check domain and mode first. URL must be valid.
     let mode = uri_mode(request.uri())?;
 
-    let stream = match connector {
+    match connector {
         Some(conn) => match conn {
             #[cfg(feature = "native-tls")]
             Connector::NativeTls(conn) => {
@@ -208,6 +523,10 @@ where
             Connector::Rustls(conn) => {
                 self::encryption::rustls::wrap_stream(stream, domain, mode, Some(conn)).await
             }
+            #[cfg(feature = "rustls-pinning")]
+            Connector::Pinned(pins) => {
+                self::encryption::rustls::wrap_stream_pinned(stream, domain, mode, pins).await
+            }
             Connector::Plain => self::encryption::plain::wrap_stream(stream, mode).await,
         },
         None => {
@@ -224,7 +543,77 @@ where
                 self::encryption::plain::wrap_stream(stream, mode).await
             }
         }
-    }?;
+    }
+}
 
-    client_async_with_config(request, stream, config).await
+/// Make sure that a server name given by the user is a valid DNS name or IP address,
+/// so that the TLS backend does not choke on it.
+///
+/// An IPv6 address may be given with or without brackets, and is passed to the TLS
+/// backend the same way as one taken from the URL by `domain()`.
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+fn check_server_name(server_name: String) -> Result<String, Error> {
+    let unbracketed = server_name.strip_prefix('[').and_then(|name| name.strip_suffix(']'));
+    let addr = unbracketed.unwrap_or(&server_name);
+    // The zone identifier of a link-local IPv6 address means nothing to TLS.
+    let addr = &addr[..addr.find('%').unwrap_or(addr.len())];
+    if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+        return Ok(crate::ipv6_server_name(addr));
+    }
+
+    let is_dns_name = server_name.len() <= 253
+        && server_name.trim_end_matches('.').split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+    if unbracketed.is_none() && (is_dns_name || server_name.parse::<std::net::Ipv4Addr>().is_ok()) {
+        Ok(server_name)
+    } else {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid TLS server name: {server_name:?}"),
+        )))
+    }
+}
+
+#[cfg(all(test, any(feature = "native-tls", feature = "__rustls-tls")))]
+mod tests {
+    use super::check_server_name;
+    use tungstenite::client::IntoClientRequest;
+
+    #[cfg(feature = "__rustls-tls")]
+    #[test]
+    fn shared_default_config() {
+        use super::encryption::rustls::default_config;
+
+        let config = default_config().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&config, &default_config().unwrap()));
+    }
+
+    #[test]
+    fn server_names() {
+        for name in ["example.com", "cdn-1.example.com.", "localhost", "127.0.0.1"] {
+            assert_eq!(check_server_name(name.to_owned()).unwrap(), name);
+        }
+        for name in ["", "exa mple.com", "-example.com", "example..com", "ex_ample.com"] {
+            assert!(check_server_name(name.to_owned()).is_err(), "{name}");
+        }
+        for name in ["[example.com]", "[127.0.0.1]", "[::1", "::1]"] {
+            assert!(check_server_name(name.to_owned()).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn ipv6_server_names() {
+        // The same as the name taken from a `wss://[::1]/` URL.
+        let expected = if cfg!(feature = "__rustls-tls") { "::1" } else { "[::1]" };
+        for name in ["::1", "[::1]", "[::1%eth0]"] {
+            assert_eq!(check_server_name(name.to_owned()).unwrap(), expected, "{name}");
+        }
+        let request = "wss://[::1]/".into_client_request().unwrap();
+        assert_eq!(crate::domain(&request).unwrap(), expected);
+    }
 }
@@ -0,0 +1,82 @@
+//! WebSockets over Unix domain sockets.
+use tokio::net::{UnixListener, UnixStream};
+
+use tungstenite::{
+    client::IntoClientRequest,
+    error::{Error, UrlError},
+    handshake::client::{Request, Response},
+    protocol::WebSocketConfig,
+};
+
+use crate::{accept_async_with_config, client_async_with_config, WebSocketStream};
+
+/// Connect to a WebSocket server listening on a Unix domain socket.
+///
+/// The URL has the form `ws+unix://<socket path>[:<request path>][#<host>]`, e.g.
+/// `ws+unix:///run/app.sock:/chat?room=1#app.example.com` connects to the socket
+/// `/run/app.sock` and requests `/chat?room=1` with the `Host` header set to
+/// `app.example.com`. The request path defaults to `/` and the host to `localhost`.
+pub async fn connect_async_unix(
+    url: &str,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<UnixStream>, Response), Error> {
+    let (path, request) = unix_request(url)?;
+    let stream = UnixStream::connect(path).await.map_err(Error::Io)?;
+    client_async_with_config(request, stream, config).await
+}
+
+/// Accept the next WebSocket connection on a Unix domain socket.
+///
+/// This waits for a client to connect to `listener` and performs the server half of
+/// the handshake, see `accept_async()`. Call it in a loop to accept connection after
+/// connection.
+pub async fn accept_async_unix(
+    listener: &UnixListener,
+    config: Option<WebSocketConfig>,
+) -> Result<WebSocketStream<UnixStream>, Error> {
+    let (stream, _) = listener.accept().await.map_err(Error::Io)?;
+    accept_async_with_config(stream, config).await
+}
+
+/// Split a `ws+unix://` URL into the socket path and the handshake request.
+fn unix_request(url: &str) -> Result<(&str, Request), Error> {
+    let rest = url.strip_prefix("ws+unix://").ok_or(Error::Url(UrlError::UnsupportedUrlScheme))?;
+    let (rest, host) = rest.split_once('#').unwrap_or((rest, "localhost"));
+    let (path, target) = match rest.find(|c: char| c == ':' || c == '?') {
+        Some(i) if rest[i..].starts_with(':') => (&rest[..i], rest[i + 1..].to_owned()),
+        Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+        None => (rest, "/".to_owned()),
+    };
+    if path.is_empty() {
+        return Err(Error::Url(UrlError::NoPathOrQuery));
+    }
+    let target = if target.starts_with('/') { target } else { format!("/{target}") };
+    let request = format!("ws://{host}{target}").into_client_request()?;
+    Ok((path, request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unix_request;
+
+    #[test]
+    fn parse_unix_url() {
+        let (path, request) = unix_request("ws+unix:///run/app.sock").unwrap();
+        assert_eq!(path, "/run/app.sock");
+        assert_eq!(request.uri(), "ws://localhost/");
+        assert_eq!(request.headers()["Host"], "localhost");
+
+        let (path, request) =
+            unix_request("ws+unix:///run/app.sock:/chat?room=1#app.example.com").unwrap();
+        assert_eq!(path, "/run/app.sock");
+        assert_eq!(request.uri(), "ws://app.example.com/chat?room=1");
+        assert_eq!(request.headers()["Host"], "app.example.com");
+
+        let (path, request) = unix_request("ws+unix://relative.sock?room=1").unwrap();
+        assert_eq!(path, "relative.sock");
+        assert_eq!(request.uri(), "ws://localhost/?room=1");
+
+        assert!(unix_request("ws:///run/app.sock").is_err());
+        assert!(unix_request("ws+unix://:/chat").is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use futures_util::SinkExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_tungstenite::{
+    tungstenite::{protocol::Role, Message},
+    WebSocketByteStream, WebSocketStream,
+};
+
+#[tokio::test]
+async fn bytes_through_binary_messages() {
+    let (client, server) = tokio::io::duplex(1024);
+    let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let mut client = WebSocketByteStream::new(client);
+    let mut server = WebSocketByteStream::new(server);
+
+    client.write_all(b"hello ").await.unwrap();
+    client.write_all(b"world").await.unwrap();
+    client.flush().await.unwrap();
+    client.get_mut().send(Message::Ping(b"skipped".to_vec())).await.unwrap();
+
+    // The messages are read as one stream of bytes, in chunks of any size.
+    let mut read = [0; 4];
+    server.read_exact(&mut read).await.unwrap();
+    assert_eq!(&read, b"hell");
+    let mut read = [0; 7];
+    server.read_exact(&mut read).await.unwrap();
+    assert_eq!(&read, b"o world");
+
+    server.write_all(b"back").await.unwrap();
+    server.flush().await.unwrap();
+    let mut read = [0; 4];
+    client.read_exact(&mut read).await.unwrap();
+    assert_eq!(&read, b"back");
+
+    client.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    assert_eq!(server.read_to_end(&mut rest).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn text_message_fails_read() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let mut server = WebSocketByteStream::new(server);
+
+    client.send(Message::text("not bytes")).await.unwrap();
+    let err = server.read_u8().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,41 @@
+use futures_util::StreamExt;
+use tokio_tungstenite::{
+    tungstenite::{
+        protocol::{frame::coding::CloseCode, Role},
+        Message,
+    },
+    Closer, WebSocketStream,
+};
+
+#[tokio::test]
+async fn closer_closes_all_streams() {
+    let closer = Closer::new();
+    let mut clients = Vec::new();
+    let mut servers = Vec::new();
+    for _ in 0..2 {
+        let (client, server) = tokio::io::duplex(1024);
+        clients.push(WebSocketStream::from_raw_socket(client, Role::Client, None).await);
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let server = server.with_closer(&closer);
+        // The servers wait for messages, they are woken up by the closer.
+        servers.push(tokio::spawn(async move {
+            let mut server = server;
+            while server.next().await.is_some() {}
+        }));
+    }
+    assert!(!closer.is_closed());
+
+    closer.clone().close();
+    assert!(closer.is_closed());
+    for client in &mut clients {
+        match client.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Away),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // The closing handshake completes.
+        assert!(client.next().await.is_none());
+    }
+    for server in servers {
+        server.await.unwrap();
+    }
+}
@@ -2,12 +2,26 @@
 
 use futures_util::{SinkExt, StreamExt};
 use log::*;
+use std::time::Duration;
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
 };
-use tokio_tungstenite::{accept_async, client_async, WebSocketStream};
-use tungstenite::Message;
+use tokio_tungstenite::{
+    accept_async, client_async, client_async_with_config, OversizedMessagePolicy, WebSocketState,
+    WebSocketStream,
+};
+use tungstenite::{
+    error::{CapacityError, Error, ProtocolError},
+    protocol::{
+        frame::{
+            coding::{CloseCode, Data, OpCode},
+            Frame,
+        },
+        CloseFrame, Role, WebSocketConfig,
+    },
+    Message,
+};
 
 async fn run_connection<S>(
     connection: WebSocketStream<S>,
@@ -104,3 +118,478 @@ async fn split_communication() {
     let messages = msg_rx.await.expect("Failed to receive messages");
     assert_eq!(messages.len(), 10);
 }
+
+#[cfg(feature = "time")]
+#[tokio::test]
+async fn close_acknowledged() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let server = tokio::spawn(async move {
+        server.send(Message::text("discarded")).await.unwrap();
+        while server.next().await.is_some() {}
+    });
+
+    let closed = client.close_with_timeout(None, Duration::from_secs(5)).await.unwrap();
+    assert!(closed);
+    server.await.unwrap();
+}
+
+#[cfg(feature = "time")]
+#[tokio::test]
+async fn close_timed_out() {
+    // The peer never reads, so it never answers the close frame.
+    let (client, _server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+    let closed = client.close_with_timeout(None, Duration::from_millis(100)).await.unwrap();
+    assert!(!closed);
+}
+
+#[tokio::test]
+async fn in_memory_handshake_with_config() {
+    let (client, server) = tokio::io::duplex(1024);
+    let config = WebSocketConfig { max_message_size: Some(16), ..Default::default() };
+    let (client, server) = tokio::join!(
+        client_async_with_config("ws://localhost/", client, Some(config)),
+        accept_async(server)
+    );
+    let (mut client, _) = client.expect("Client failed to connect");
+    let mut server = server.expect("Failed to handshake with connection");
+    assert_eq!(client.get_config().max_message_size, Some(16));
+
+    server.send(Message::text("short")).await.unwrap();
+    server.send(Message::text("longer than the limit")).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::text("short"));
+    match client.next().await {
+        Some(Err(Error::Capacity(CapacityError::MessageTooLong { .. }))) => {}
+        other => panic!("unexpected result: {:?}", other.map(|r| r.map(|_| ()))),
+    }
+}
+
+#[tokio::test]
+async fn half_close() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    client.close(None).await.unwrap();
+    match client.send(Message::text("too late")).await {
+        Err(Error::Protocol(ProtocolError::SendAfterClosing)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The server sends a last message before it notices the close frame.
+    server.send(Message::text("late reply")).await.unwrap();
+    let server = tokio::spawn(async move {
+        assert!(matches!(server.next().await, Some(Ok(Message::Close(None)))));
+        // Answering the close frame completes the closing handshake.
+        assert!(server.next().await.is_none());
+    });
+
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::text("late reply"));
+    assert!(matches!(client.next().await, Some(Ok(Message::Close(None)))));
+    assert!(client.next().await.is_none());
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn poll_based_api() {
+    use futures_util::future::poll_fn;
+
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    // Larger than the pipe, so writing only completes while the server reads.
+    let mut message = Some(Message::binary(vec![7; 1000]));
+    let writing = tokio::spawn(async move {
+        poll_fn(|cx| client.poll_write_message(cx, &mut message)).await.unwrap();
+        assert!(message.is_none());
+        poll_fn(|cx| client.poll_close(cx)).await.unwrap();
+        client
+    });
+
+    let received = poll_fn(|cx| server.poll_read_message(cx)).await.unwrap().unwrap();
+    assert_eq!(received, Message::binary(vec![7; 1000]));
+    let received = poll_fn(|cx| server.poll_read_message(cx)).await.unwrap().unwrap();
+    assert!(matches!(received, Message::Close(None)));
+    assert!(poll_fn(|cx| server.poll_read_message(cx)).await.is_none());
+    writing.await.unwrap();
+}
+
+#[tokio::test]
+async fn batched_reads() {
+    use futures_util::future::poll_fn;
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    for i in 0..5 {
+        client.feed(Message::text(i.to_string())).await.unwrap();
+    }
+    // A fragmented message is only part of a batch once complete.
+    client
+        .feed(Message::Frame(Frame::message(b"par".to_vec(), OpCode::Data(Data::Text), false)))
+        .await
+        .unwrap();
+    client.flush().await.unwrap();
+
+    let batch = poll_fn(|cx| server.poll_next_batch(cx, 3)).await;
+    let batch: Vec<_> = batch.into_iter().map(Result::unwrap).collect();
+    assert_eq!(batch, ["0", "1", "2"].map(Message::text));
+    let batch = poll_fn(|cx| server.poll_next_batch(cx, 10)).await;
+    let batch: Vec<_> = batch.into_iter().map(Result::unwrap).collect();
+    assert_eq!(batch, ["3", "4"].map(Message::text));
+
+    client
+        .send(Message::Frame(Frame::message(b"tial".to_vec(), OpCode::Data(Data::Continue), true)))
+        .await
+        .unwrap();
+    client.close(None).await.unwrap();
+    let batch = poll_fn(|cx| server.poll_next_batch(cx, 10)).await;
+    let batch: Vec<_> = batch.into_iter().map(Result::unwrap).collect();
+    assert_eq!(batch, [Message::text("partial"), Message::Close(None)]);
+    assert!(poll_fn(|cx| server.poll_next_batch(cx, 10)).await.is_empty());
+}
+
+#[tokio::test]
+async fn peek() {
+    use futures_util::future::poll_fn;
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    client.send(Message::text("first")).await.unwrap();
+    client.send(Message::text("second")).await.unwrap();
+    client.close(None).await.unwrap();
+
+    for _ in 0..2 {
+        let peeked = poll_fn(|cx| server.poll_peek(cx).map(|m| m.map(|m| m.unwrap().clone())));
+        assert_eq!(peeked.await, Some(Message::text("first")));
+    }
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("first"));
+    let peeked = poll_fn(|cx| server.poll_peek(cx).map(|m| m.map(|m| m.unwrap().clone())));
+    assert_eq!(peeked.await, Some(Message::text("second")));
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("second"));
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Close(None));
+    assert!(poll_fn(|cx| server.poll_peek(cx).map(|m| m.is_none())).await);
+    assert!(server.next().await.is_none());
+}
+
+#[tokio::test]
+async fn config_changed() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    client.send(Message::text("0123456789")).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("0123456789"));
+
+    server.set_config(|config| config.max_message_size = Some(5));
+    assert_eq!(server.get_config().max_message_size, Some(5));
+    client.send(Message::text("0123456789")).await.unwrap();
+    match server.next().await {
+        Some(Err(Error::Capacity(CapacityError::MessageTooLong { .. }))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "sync")]
+#[tokio::test]
+async fn shared_sender() {
+    let (client, server) = tokio::io::duplex(1024);
+    let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let (sender, _incoming, writer) = client.split_shared(2);
+    let writer = tokio::spawn(writer);
+
+    let tasks: Vec<_> = (0..3)
+        .map(|i| {
+            let sender = sender.clone();
+            tokio::spawn(async move { sender.send(Message::text(format!("{i}"))).await })
+        })
+        .collect();
+    drop(sender);
+    for task in tasks {
+        task.await.unwrap().expect("Failed to send");
+    }
+
+    let mut received = vec![];
+    while let Some(message) = server.next().await {
+        match message.unwrap() {
+            Message::Text(text) => received.push(text),
+            Message::Close(_) => {}
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+    received.sort();
+    assert_eq!(received, ["0", "1", "2"]);
+    writer.await.unwrap();
+}
+
+#[cfg(feature = "sync")]
+#[tokio::test]
+async fn shared_sender_error() {
+    let (client, server) = tokio::io::duplex(1024);
+    let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let (sender, _incoming, writer) = client.split_shared(1);
+    let writer = tokio::spawn(writer);
+    drop(server);
+
+    match sender.send(Message::text("lost")).await {
+        Err(Error::Io(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    writer.await.unwrap();
+    match sender.send(Message::text("lost")).await {
+        Err(Error::AlreadyClosed) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// An unmasked text frame, as sent by a non-compliant client.
+const UNMASKED_FRAME: &[u8] = b"\x81\x05hello";
+
+#[tokio::test]
+async fn unmasked_frames_rejected() {
+    let (mut client, server) = tokio::io::duplex(1024);
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    client.write_all(UNMASKED_FRAME).await.unwrap();
+    match server.next().await {
+        Some(Err(Error::Protocol(ProtocolError::UnmaskedFrameFromClient))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn unmasked_frames_accepted() {
+    let (mut client, server) = tokio::io::duplex(1024);
+    let config = WebSocketConfig { accept_unmasked_frames: true, ..Default::default() };
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, Some(config)).await;
+    client.write_all(UNMASKED_FRAME).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("hello"));
+
+    // Masked frames are still accepted as well.
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, Some(config)).await;
+    client.send(Message::text("hello")).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("hello"));
+}
+
+#[tokio::test]
+async fn close_states() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    assert_eq!(client.state(), WebSocketState::Open);
+    assert_eq!(server.state(), WebSocketState::Open);
+
+    client.close(None).await.unwrap();
+    assert_eq!(client.state(), WebSocketState::ClosingByUs);
+
+    assert!(matches!(server.next().await, Some(Ok(Message::Close(_)))));
+    assert_eq!(server.state(), WebSocketState::ClosingByPeer);
+    assert!(server.next().await.is_none());
+    assert_eq!(server.state(), WebSocketState::Closed);
+
+    assert!(matches!(client.next().await, Some(Ok(Message::Close(_)))));
+    assert_eq!(client.state(), WebSocketState::Closed);
+    assert!(client.next().await.is_none());
+}
+
+#[tokio::test]
+async fn send_pong() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    client.send_pong(vec![7; 125]).await.unwrap();
+    match client.send_pong(vec![7; 126]).await {
+        Err(Error::Protocol(ProtocolError::ControlFrameTooBig)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    client.send(Message::text("done")).await.unwrap();
+
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Pong(vec![7; 125]));
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("done"));
+}
+
+#[tokio::test]
+async fn close_aborted() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+    let frame = CloseFrame { code: CloseCode::Away, reason: "shutting down".into() };
+    assert!(client.abort_with_close(Some(frame.clone())));
+    assert_eq!(client.state(), WebSocketState::Closed);
+    assert!(client.next().await.is_none());
+    assert!(client.send(Message::text("late")).await.is_err());
+    drop(client);
+
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Close(Some(frame)));
+}
+
+#[tokio::test]
+async fn close_frame_kept() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let server = tokio::spawn(async move {
+        let frame = CloseFrame { code: CloseCode::Away, reason: "shutting down".into() };
+        server.close(Some(frame)).await.unwrap();
+        while server.next().await.is_some() {}
+    });
+
+    assert!(client.close_frame().is_none());
+    while client.next().await.is_some() {}
+    let frame = client.close_frame().expect("No close frame");
+    assert_eq!(frame.code, CloseCode::Away);
+    assert_eq!(frame.reason, "shutting down");
+    server.await.unwrap();
+}
+
+/// Read what a server sends as raw `frames`, stopping at the first error.
+async fn read_raw_frames(frames: &[u8]) -> Vec<Result<Message, Error>> {
+    let (client, mut server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    server.write_all(frames).await.unwrap();
+    server.shutdown().await.unwrap();
+    let mut items = vec![];
+    while let Some(item) = client.next().await {
+        let failed = item.is_err();
+        items.push(item);
+        if failed {
+            break;
+        }
+    }
+    items
+}
+
+#[tokio::test]
+async fn fragmentation() {
+    // Autobahn 5.6: a text message in two fragments with a ping in between.
+    let items = read_raw_frames(b"\x01\x03Hel\x89\x04ping\x80\x02lo").await;
+    assert!(matches!(&items[0], Ok(Message::Ping(payload)) if payload == b"ping"));
+    assert!(matches!(&items[1], Ok(Message::Text(text)) if text == "Hello"));
+
+    // Autobahn 5.9: a continuation frame without a message to continue.
+    let items = read_raw_frames(b"\x80\x02lo\x81\x05Hello").await;
+    match &items[..] {
+        [Err(Error::Protocol(ProtocolError::UnexpectedContinueFrame))] => {}
+        other => panic!("unexpected items: {:?}", other),
+    }
+
+    // Autobahn 5.18: a new text message before the end of the fragmented one.
+    let items = read_raw_frames(b"\x01\x03Hel\x81\x02lo").await;
+    match &items[..] {
+        [Err(Error::Protocol(ProtocolError::ExpectedFragment(_)))] => {}
+        other => panic!("unexpected items: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn oversized_message_policies() {
+    let config = WebSocketConfig { max_message_size: Some(16), ..Default::default() };
+    for policy in [OversizedMessagePolicy::Error, OversizedMessagePolicy::CloseWith1009] {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = WebSocketStream::from_raw_socket(client, Role::Client, Some(config))
+            .await
+            .with_oversized_message_policy(policy);
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+        server.send(Message::text("x".repeat(100))).await.unwrap();
+        match client.next().await {
+            Some(Err(Error::Capacity(CapacityError::MessageTooLong { .. }))) => {}
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert!(client.next().await.is_none());
+        drop(client);
+
+        match (policy, server.next().await) {
+            (OversizedMessagePolicy::CloseWith1009, Some(Ok(Message::Close(Some(frame))))) => {
+                assert_eq!(frame.code, CloseCode::Size)
+            }
+            (OversizedMessagePolicy::Error, Some(Err(Error::Protocol(_)))) => {}
+            (_, other) => panic!("unexpected item: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn fragmented_read_cancelled() {
+    let (client, mut server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+    // The first fragment of a text message, then nothing for a while.
+    server.write_all(b"\x01\x03Hel").await.unwrap();
+    let cancelled = tokio::time::timeout(Duration::from_millis(100), client.next()).await;
+    assert!(cancelled.is_err());
+
+    server.write_all(b"\x00\x01l").await.unwrap();
+    let cancelled = tokio::time::timeout(Duration::from_millis(100), client.next()).await;
+    assert!(cancelled.is_err());
+
+    // The partial message has been kept, the read completes with the last fragment.
+    server.write_all(b"\x80\x01o").await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::text("Hello"));
+}
+
+#[tokio::test]
+async fn ping_flood() {
+    // The pongs of the server fill the pipe quickly since the client never reads them.
+    let (client, server) = tokio::io::duplex(256);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None)
+        .await
+        .with_max_pending_pongs(4);
+
+    tokio::spawn(async move {
+        for _ in 0..100 {
+            if client.send(Message::Ping(vec![0; 100])).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut pings = 0;
+    loop {
+        match server.next().await {
+            Some(Ok(Message::Ping(_))) => {
+                pings += 1;
+                assert!(server.pending_pongs() <= 4);
+            }
+            Some(Err(Error::Protocol(ProtocolError::ControlFrameTooBig))) => break,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    assert!(pings < 100);
+    assert!(server.next().await.is_none());
+}
+
+#[tokio::test]
+async fn pings_answered_in_time() {
+    // The pongs are written as the server reads, so they never pile up.
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None)
+        .await
+        .with_max_pending_pongs(1);
+
+    for i in 0..10u8 {
+        client.send(Message::Ping(vec![i; 10])).await.unwrap();
+    }
+    client.close(None).await.unwrap();
+    for i in 0..10u8 {
+        assert_eq!(server.next().await.unwrap().unwrap(), Message::Ping(vec![i; 10]));
+        assert_eq!(server.pending_pongs(), 1);
+    }
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Close(None));
+    for i in 0..10u8 {
+        assert_eq!(client.next().await.unwrap().unwrap(), Message::Pong(vec![i; 10]));
+    }
+}
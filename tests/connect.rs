@@ -0,0 +1,690 @@
+#![cfg(feature = "connect")]
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_tungstenite::{
+    accept_async, accept_hdr_async, connect_async, connect_async_to_addr,
+    connect_async_with_config, connect_async_with_happy_eyeballs, connect_async_with_info,
+    connect_async_with_local_addr, connect_async_with_redirects, connect_async_with_resolver,
+    connect_async_with_retry, connect_async_with_socket_config, connect_transport_async,
+    AddressFamily, Backoff, ClientBuilder, MaybeTlsStream, Resolver, Resolving, RetryPolicy,
+    SocketConfig, TcpKeepalive,
+};
+use tungstenite::{
+    client::IntoClientRequest,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{
+        header::{HeaderValue, AUTHORIZATION, COOKIE, HOST, PROXY_AUTHORIZATION, UPGRADE},
+        HeaderMap,
+    },
+    Error,
+};
+
+/// Accept a single WebSocket connection and wait for it to be closed.
+async fn accept_one(listener: TcpListener) {
+    let (connection, _) = listener.accept().await.expect("No connections to accept");
+    let mut stream = accept_async(connection).await.expect("Failed to handshake");
+    while stream.next().await.is_some() {}
+}
+
+#[tokio::test]
+async fn happy_eyeballs_falls_back() {
+    let _ = env_logger::try_init();
+
+    // `localhost` may resolve to both `::1` and `127.0.0.1`, but only the latter is
+    // listening, so the attempt to `::1` (if any) is refused.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(accept_one(listener));
+
+    let url = format!("ws://localhost:{port}/");
+    let (stream, _) =
+        connect_async_with_happy_eyeballs(url, None, false).await.expect("Failed to connect");
+    drop(stream);
+}
+
+/// Resolves every host to the same address.
+struct StaticResolver(SocketAddr);
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, _host: &str, _port: u16) -> Resolving {
+        let addr = self.0;
+        Box::pin(async move { Ok(vec![addr]) })
+    }
+}
+
+#[tokio::test]
+async fn custom_resolver() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let resolver = Arc::new(StaticResolver(listener.local_addr().unwrap()));
+    tokio::spawn(accept_one(listener));
+
+    let (_stream, response) =
+        connect_async_with_resolver("ws://nonexistent.invalid/", None, false, resolver)
+            .await
+            .expect("Failed to connect");
+    assert_eq!(response.status(), 101);
+}
+
+#[tokio::test]
+async fn bind_local_addr() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (peer_tx, peer_rx) = futures_channel::oneshot::channel();
+    tokio::spawn(async move {
+        let (connection, peer) = listener.accept().await.expect("No connections to accept");
+        peer_tx.send(peer).unwrap();
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while stream.next().await.is_some() {}
+    });
+
+    let local_addr = "127.0.0.1:0".parse().unwrap();
+    let (stream, _) =
+        connect_async_with_local_addr(format!("ws://{addr}/"), None, false, local_addr)
+            .await
+            .expect("Failed to connect");
+    let client_addr = match stream.get_ref() {
+        MaybeTlsStream::Plain(tcp) => tcp.local_addr().unwrap(),
+        _ => unreachable!(),
+    };
+    assert_eq!(peer_rx.await.unwrap(), client_addr);
+    assert_eq!(client_addr.ip(), local_addr.ip());
+}
+
+#[tokio::test]
+async fn bind_mismatched_family() {
+    let local_addr = "[::1]:0".parse().unwrap();
+    let result = connect_async_with_local_addr("ws://127.0.0.1:1/", None, false, local_addr).await;
+    match result {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Resolves every host to the given addresses.
+struct ListResolver(Vec<SocketAddr>);
+
+impl Resolver for ListResolver {
+    fn resolve(&self, _host: &str, _port: u16) -> Resolving {
+        let addrs = self.0.clone();
+        Box::pin(async move { Ok(addrs) })
+    }
+}
+
+#[tokio::test]
+async fn preferred_address_family() {
+    let _ = env_logger::try_init();
+
+    let v4 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let v6 = match TcpListener::bind("[::1]:0").await {
+        Ok(listener) => listener,
+        // No IPv6 on this machine.
+        Err(_) => return,
+    };
+    let addrs = vec![v6.local_addr().unwrap(), v4.local_addr().unwrap()];
+    tokio::spawn(async move {
+        tokio::join!(accept_one(v4), accept_one(v6));
+    });
+
+    for family in [AddressFamily::Ipv4, AddressFamily::Ipv6] {
+        let (stream, _) = ClientBuilder::new("ws://dual-stack.invalid/")
+            .resolver(Arc::new(ListResolver(addrs.clone())))
+            .address_family(family)
+            .connect()
+            .await
+            .expect("Failed to connect");
+        let peer_addr = stream.get_ref().peer_addr().unwrap();
+        assert_eq!(peer_addr.is_ipv6(), family == AddressFamily::Ipv6);
+    }
+
+    // The other family is used when the preferred one is unreachable.
+    let closed = TcpListener::bind("[::1]:0").await.unwrap().local_addr().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(accept_one(listener));
+    let (stream, _) = ClientBuilder::new("ws://dual-stack.invalid/")
+        .resolver(Arc::new(ListResolver(vec![closed, addr])))
+        .address_family(AddressFamily::Ipv6)
+        .connect()
+        .await
+        .expect("Failed to connect");
+    assert_eq!(stream.get_ref().peer_addr().unwrap(), addr);
+}
+
+#[tokio::test]
+async fn socket_addresses() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let (peer_tx, peer_rx) = futures_channel::oneshot::channel();
+    tokio::spawn(async move {
+        let (connection, peer_addr) = listener.accept().await.expect("No connections to accept");
+        peer_tx.send(peer_addr).unwrap();
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while stream.next().await.is_some() {}
+    });
+
+    let url = format!("ws://{server_addr}/");
+    let (stream, _) = connect_async_with_config(url, None, false).await.expect("Failed to connect");
+    assert_eq!(stream.get_ref().peer_addr().unwrap(), server_addr);
+    assert_eq!(stream.get_ref().local_addr().unwrap(), peer_rx.await.unwrap());
+}
+
+#[tokio::test]
+async fn socket_options() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(accept_one(listener));
+
+    let keepalive = TcpKeepalive { idle: Some(Duration::from_secs(60)), ..Default::default() };
+    let socket =
+        SocketConfig { keepalive: Some(keepalive), reuse_address: true, ..Default::default() };
+    let (stream, _) = connect_async_with_socket_config(url, None, false, socket)
+        .await
+        .expect("Failed to connect");
+    let tcp = socket2::SockRef::from(stream.get_ref().get_ref());
+    assert!(tcp.keepalive().unwrap());
+    assert!(tcp.reuse_address().unwrap());
+}
+
+const RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    backoff: Backoff {
+        base: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        jitter: 0.0,
+    },
+};
+
+/// Answer the first connections with the given responses (closing the connection right
+/// away for `None`), then accept a WebSocket connection. Counts the connections.
+async fn flaky_server(
+    listener: TcpListener,
+    responses: &'static [Option<&'static str>],
+    connections: Arc<AtomicUsize>,
+) {
+    for response in responses {
+        let (mut connection, _) = listener.accept().await.expect("No connections to accept");
+        connections.fetch_add(1, Ordering::SeqCst);
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(connection.read_u8().await.expect("Failed to read request head"));
+        }
+        if let Some(response) = response {
+            connection.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+    let (connection, _) = listener.accept().await.expect("No connections to accept");
+    connections.fetch_add(1, Ordering::SeqCst);
+    let mut stream = accept_async(connection).await.expect("Failed to handshake");
+    while stream.next().await.is_some() {}
+}
+
+#[tokio::test]
+async fn retry_transient_failures() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let responses = &[None, Some("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n")];
+    tokio::spawn(flaky_server(listener, responses, connections.clone()));
+
+    let (stream, _) =
+        connect_async_with_retry(url, None, false, RETRY).await.expect("Failed to connect");
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+    drop(stream);
+}
+
+#[tokio::test]
+async fn no_retry_on_rejection() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let responses = &[Some("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")];
+    tokio::spawn(flaky_server(listener, responses, connections.clone()));
+
+    match connect_async_with_retry(url, None, false, RETRY).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 404),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn builder_combines_options() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let resolver = Arc::new(StaticResolver(listener.local_addr().unwrap()));
+    let connections = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(flaky_server(listener, &[None], connections.clone()));
+
+    let (stream, response) = ClientBuilder::new("ws://nonexistent.invalid/")
+        .disable_nagle(true)
+        .resolver(resolver)
+        .retry(RETRY)
+        .timeout(Duration::from_secs(5))
+        .socket_config(SocketConfig { reuse_address: true, ..Default::default() })
+        .connect()
+        .await
+        .expect("Failed to connect");
+    assert_eq!(response.status(), 101);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    match stream.get_ref() {
+        MaybeTlsStream::Plain(socket) => assert!(socket.nodelay().unwrap()),
+        _ => panic!("unexpected TLS stream"),
+    }
+}
+
+#[tokio::test]
+async fn builder_invalid_url() {
+    match tokio_tungstenite::connect("not a url").connect().await {
+        Err(Error::HttpFormat(_)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn host_header_override() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let _ = tx.send(request.headers()["Host"].clone());
+            Ok(response)
+        };
+        let _stream = accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+        rx.await.unwrap()
+    });
+
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert("Host", "prod.example.com".parse().unwrap());
+    let (_stream, _) = connect_async(request).await.expect("Failed to connect");
+    assert_eq!(server.await.unwrap(), "prod.example.com");
+}
+
+#[tokio::test]
+async fn pre_resolved_addr() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let _ = tx.send(request.headers()["Host"].clone());
+            Ok(response)
+        };
+        let _stream = accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+        rx.await.unwrap()
+    });
+
+    let (stream, _) = connect_async_to_addr("ws://cached.invalid:8080/", None, false, addr)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(stream.get_ref().peer_addr().unwrap(), addr);
+    assert_eq!(server.await.unwrap(), "cached.invalid:8080");
+}
+
+#[tokio::test]
+async fn connect_info() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(accept_one(listener));
+
+    let url = format!("ws://localhost:{}/", addr.port());
+    let (_stream, response, info) =
+        connect_async_with_info(url, None, false).await.expect("Failed to connect");
+    assert_eq!(response.status(), 101);
+    assert_eq!(info.peer_addr, addr);
+    assert_eq!(info.tunneled, None);
+    assert!(info.proxy.is_none());
+    assert!(!info.tls);
+}
+
+#[tokio::test]
+async fn connect_timings() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        // Slow down the WebSocket handshake only.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while stream.next().await.is_some() {}
+    });
+
+    let (_stream, _, info) = connect_async_with_info(format!("ws://{addr}/"), None, false)
+        .await
+        .expect("Failed to connect");
+    let timings = info.timings;
+    assert!(timings.tcp_connect > Duration::ZERO);
+    assert!(timings.websocket_handshake >= Duration::from_millis(100));
+    assert!(timings.tcp_connect < timings.websocket_handshake);
+    assert_eq!(timings.proxy_handshake, None);
+    assert_eq!(timings.tls_handshake, None);
+}
+
+/// Answer every connection with a redirection to `location`.
+async fn redirecting_server(listener: TcpListener, location: String) {
+    while let Ok((mut connection, _)) = listener.accept().await {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(connection.read_u8().await.expect("Failed to read request head"));
+        }
+        let response =
+            format!("HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\n\r\n");
+        connection.write_all(response.as_bytes()).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn follow_redirect() {
+    let _ = env_logger::try_init();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_url = format!("ws://{}/target", target.local_addr().unwrap());
+    tokio::spawn(accept_one(target));
+    let redirecting = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", redirecting.local_addr().unwrap());
+    tokio::spawn(redirecting_server(redirecting, target_url));
+
+    let (_stream, response) =
+        connect_async_with_redirects(url, None, false, 1).await.expect("Failed to connect");
+    assert_eq!(response.status(), 101);
+}
+
+/// Accept a single WebSocket connection on `stream` and return the headers of its
+/// request.
+async fn accept_headers<S>(stream: S) -> HeaderMap
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (tx, rx) = futures_channel::oneshot::channel();
+    let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        let _ = tx.send(request.headers().clone());
+        Ok(response)
+    };
+    let _stream = accept_hdr_async(stream, callback).await.expect("Failed to handshake");
+    rx.await.unwrap()
+}
+
+#[tokio::test]
+async fn redirect_to_another_host_drops_credentials() {
+    let _ = env_logger::try_init();
+
+    let request_with_credentials = |url: String| {
+        let mut request = url.into_client_request().unwrap();
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert(PROXY_AUTHORIZATION, HeaderValue::from_static("Basic c2VjcmV0"));
+        headers.insert(COOKIE, HeaderValue::from_static("session=secret"));
+        headers.insert("X-Client", HeaderValue::from_static("test"));
+        request
+    };
+    let assert_no_credentials = |headers: HeaderMap| {
+        assert!(headers.get(AUTHORIZATION).is_none());
+        assert!(headers.get(PROXY_AUTHORIZATION).is_none());
+        assert!(headers.get(COOKIE).is_none());
+        assert_eq!(headers["X-Client"], "test");
+    };
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_url = format!("ws://{}/target", target.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = target.accept().await.unwrap();
+        accept_headers(connection).await
+    });
+    let redirecting = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", redirecting.local_addr().unwrap());
+    tokio::spawn(redirecting_server(redirecting, target_url));
+
+    connect_async_with_redirects(request_with_credentials(url), None, false, 1)
+        .await
+        .expect("Failed to connect");
+    assert_no_credentials(server.await.unwrap());
+
+    // To a `wss://` URL, whose certificate would not match the server name meant for
+    // the original host.
+    #[cfg(feature = "__rustls-tls")]
+    {
+        use rustls::{Certificate, PrivateKey, ServerConfig};
+        use tokio_rustls::TlsAcceptor;
+        use tokio_tungstenite::Connector;
+
+        let cert = Certificate(include_bytes!("certs/ca-signed.der").to_vec());
+        let key = PrivateKey(include_bytes!("certs/ca-signed.key.der").to_vec());
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_url = format!("wss://localhost:{}/target", target.local_addr().unwrap().port());
+        let server = tokio::spawn(async move {
+            let (connection, _) = target.accept().await.unwrap();
+            let stream = acceptor.accept(connection).await.expect("Failed to accept TLS");
+            accept_headers(stream).await
+        });
+        let redirecting = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/", redirecting.local_addr().unwrap());
+        tokio::spawn(redirecting_server(redirecting, target_url));
+
+        let connector =
+            Connector::with_root_certificates(include_bytes!("certs/ca.pem").as_slice()).unwrap();
+        ClientBuilder::new(request_with_credentials(url))
+            .connector(connector)
+            .server_name("original.invalid")
+            .max_redirects(1)
+            .connect()
+            .await
+            .expect("Failed to connect");
+        assert_no_credentials(server.await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn redirect_loop() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(redirecting_server(listener, url.clone()));
+
+    match connect_async_with_redirects(url.as_str(), None, false, 5).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 302),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    // Without following redirections.
+    match connect_async_with_config(url, None, false).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 302),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn builder_headers() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let _ = tx.send(request.headers()["Authorization"].clone());
+            Ok(response)
+        };
+        let _stream = accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+        rx.await.unwrap()
+    });
+
+    let headers = vec![(AUTHORIZATION, HeaderValue::from_static("Bearer token"))];
+    let (_stream, _) = ClientBuilder::new(url.as_str())
+        .headers(headers)
+        .connect()
+        .await
+        .expect("Failed to connect");
+    assert_eq!(server.await.unwrap(), "Bearer token");
+
+    // The handshake sends these already, twice would make the server reject it.
+    for name in [UPGRADE, HOST] {
+        let headers = vec![(name, HeaderValue::from_static("h2c"))];
+        match ClientBuilder::new(url.as_str()).headers(headers).connect().await {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
+
+#[tokio::test]
+async fn builder_origin() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let _ = tx.send(request.headers()["Origin"].clone());
+            Ok(response)
+        };
+        let _stream = accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+        rx.await.unwrap()
+    });
+
+    let builder = ClientBuilder::new(url.as_str()).origin("https://example.com");
+    let (_stream, _) = builder.connect().await.expect("Failed to connect");
+    assert_eq!(server.await.unwrap(), "https://example.com");
+
+    match ClientBuilder::new(url).origin("https://example.com/app").connect().await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn builder_on_request() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let headers = request.headers();
+            let _ = tx.send((headers["Sec-WebSocket-Key"].clone(), headers["X-Signature"].clone()));
+            Ok(response)
+        };
+        let _stream = accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+        rx.await.unwrap()
+    });
+
+    let builder = ClientBuilder::new(url).on_request(|request| {
+        // Sign the final request, which already has its key.
+        let key = request.headers()["Sec-WebSocket-Key"].to_str().unwrap().to_owned();
+        let signature = format!("signed:{key}").parse().unwrap();
+        request.headers_mut().insert("X-Signature", signature);
+    });
+    let (_stream, _) = builder.connect().await.expect("Failed to connect");
+    let (key, signature) = server.await.unwrap();
+    assert_eq!(signature, format!("signed:{}", key.to_str().unwrap()).as_str());
+}
+
+#[tokio::test]
+async fn credentials_in_url() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let mut headers = vec![];
+        for _ in 0..2 {
+            let (connection, _) = listener.accept().await.unwrap();
+            let (tx, rx) = futures_channel::oneshot::channel();
+            let callback =
+                |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+                    let _ = tx.send(request.headers().clone());
+                    Ok(response)
+                };
+            let _stream =
+                accept_hdr_async(connection, callback).await.expect("Failed to handshake");
+            headers.push(rx.await.unwrap());
+        }
+        headers
+    });
+
+    let url = format!("ws://user:secret@{addr}/");
+    let (_stream, _) = connect_async(url.as_str()).await.expect("Failed to connect");
+    let builder = ClientBuilder::new(url).basic_auth_from_url();
+    let (_stream, _) = builder.connect().await.expect("Failed to connect");
+
+    let headers = server.await.unwrap();
+    for headers in &headers {
+        assert_eq!(headers["Host"], addr.to_string().as_str());
+        assert!(headers.iter().all(|(name, value)| name == AUTHORIZATION
+            || !value.to_str().unwrap_or_default().contains("secret")));
+    }
+    assert!(!headers[0].contains_key(AUTHORIZATION));
+    // "user:secret" in base64.
+    assert_eq!(headers[1][AUTHORIZATION], "Basic dXNlcjpzZWNyZXQ=");
+}
+
+#[tokio::test]
+async fn transport_without_handshake() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(accept_one(listener));
+
+    let url = format!("ws://localhost:{}/chat", addr.port());
+    let (stream, request) =
+        connect_transport_async(url, false, None).await.expect("Failed to connect");
+    let mut tcp = match stream {
+        MaybeTlsStream::Plain(tcp) => tcp,
+        _ => panic!("expected a plain TCP stream"),
+    };
+    assert_eq!(tcp.peer_addr().unwrap(), addr);
+    assert_eq!(request.uri().path(), "/chat");
+
+    let mut handshake = format!("GET {} HTTP/1.1\r\n", request.uri().path());
+    for (name, value) in request.headers() {
+        handshake.push_str(&format!("{}: {}\r\n", name, value.to_str().unwrap()));
+    }
+    handshake.push_str("\r\n");
+    tcp.write_all(handshake.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(tcp.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101"), "unexpected response: {response}");
+}
@@ -0,0 +1,233 @@
+#![cfg(feature = "rustls-early-data")]
+
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, Error as TlsError, PrivateKey, ServerConfig, ServerConnection,
+    ServerName,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{accept_async, client_async_tls_with_config, Connector, MaybeTlsStream};
+
+/// A self-signed certificate for `localhost`.
+const CERT: &[u8] = include_bytes!("certs/localhost.der");
+const KEY: &[u8] = include_bytes!("certs/localhost.key.der");
+
+/// Trusts any certificate, certificates are tested in `pinning.rs`.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn server_config() -> ServerConfig {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+        .unwrap();
+    config.max_early_data_size = 16384;
+    config
+}
+
+fn connector() -> Connector {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(NoVerification))
+        .with_no_client_auth();
+    config.enable_early_data = true;
+    Connector::Rustls(Arc::new(config))
+}
+
+/// Records what the client sends before the server answers, i.e. its first flight.
+struct FirstFlight<S> {
+    stream: S,
+    sent: Arc<Mutex<Vec<u8>>>,
+    answered: bool,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FirstFlight<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.answered = true;
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FirstFlight<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let result = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if !self.answered {
+                self.sent.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Whether the TLS records of a first flight include application data, which can only
+/// be early data before the server has answered.
+fn sent_early_data(first_flight: &[u8]) -> bool {
+    let mut records = first_flight;
+    while records.len() >= 5 {
+        if records[0] == 23 {
+            return true;
+        }
+        let len = usize::from(u16::from_be_bytes([records[3], records[4]]));
+        records = &records[(5 + len).min(records.len())..];
+    }
+    false
+}
+
+/// Connect to `addr` with `connector`, returning whether early data was sent and
+/// accepted.
+async fn connect_with_early_data(addr: std::net::SocketAddr, connector: Connector) -> (bool, bool) {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let stream = FirstFlight { stream: tcp, sent: sent.clone(), answered: false };
+    let (stream, response) =
+        client_async_tls_with_config("wss://localhost/", stream, None, Some(connector))
+            .await
+            .expect("Client failed to connect");
+    assert_eq!(response.status(), 101);
+    let accepted = match stream.get_ref() {
+        MaybeTlsStream::Rustls(tls) => tls.get_ref().1.is_early_data_accepted(),
+        _ => panic!("not a rustls stream"),
+    };
+    let attempted = sent_early_data(&sent.lock().unwrap());
+    (attempted, accepted)
+}
+
+#[tokio::test]
+async fn early_data_rejected() {
+    // The tickets of the first connection allow early data, but the server no longer
+    // accepts it afterwards: the request must be sent again after the TLS handshake of
+    // the resumed session.
+    let mut config = server_config();
+    let first = TlsAcceptor::from(Arc::new(config.clone()));
+    config.max_early_data_size = 0;
+    let rest = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut acceptor = first;
+        while let Ok((connection, _)) = listener.accept().await {
+            if let Ok(stream) = acceptor.accept(connection).await {
+                let _ = accept_async(stream).await;
+            }
+            acceptor = rest.clone();
+        }
+    });
+
+    let connector = connector();
+    assert_eq!(connect_with_early_data(addr, connector.clone()).await, (false, false));
+    assert_eq!(connect_with_early_data(addr, connector).await, (true, false));
+}
+
+/// Reads the early data before the rest of the TLS stream.
+struct WithEarlyData {
+    early_data: std::io::Cursor<Vec<u8>>,
+    stream: rustls::StreamOwned<ServerConnection, std::net::TcpStream>,
+}
+
+impl Read for WithEarlyData {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.early_data.read(buf)? {
+            0 => self.stream.read(buf),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for WithEarlyData {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[tokio::test]
+async fn early_data_accepted() {
+    // `tokio-rustls` does not read early data on the server side, so the server drives a
+    // `rustls::ServerConnection` itself.
+    let config = Arc::new(server_config());
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for tcp in listener.incoming() {
+            let mut tcp = tcp.unwrap();
+            let mut connection = ServerConnection::new(config.clone()).unwrap();
+            let mut early_data = Vec::new();
+            while connection.is_handshaking() {
+                while connection.wants_write() {
+                    connection.write_tls(&mut tcp).unwrap();
+                }
+                if connection.read_tls(&mut tcp).unwrap() == 0 {
+                    break;
+                }
+                connection.process_new_packets().unwrap();
+                if let Some(mut data) = connection.early_data() {
+                    data.read_to_end(&mut early_data).unwrap();
+                }
+            }
+            let _ = tx.send(early_data.clone());
+            let stream = WithEarlyData {
+                early_data: std::io::Cursor::new(early_data),
+                stream: rustls::StreamOwned::new(connection, tcp),
+            };
+            let _ = tungstenite::accept(stream);
+        }
+    });
+
+    let connector = connector();
+    assert_eq!(connect_with_early_data(addr, connector.clone()).await, (false, false));
+    assert!(rx.recv().unwrap().is_empty());
+    assert_eq!(connect_with_early_data(addr, connector).await, (true, true));
+    assert!(rx.recv().unwrap().starts_with(b"GET / HTTP/1.1\r\n"));
+}
@@ -1,7 +1,24 @@
 #![cfg(feature = "handshake")]
 
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, client_async};
+#[cfg(feature = "time")]
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::{
+    accept_async, accept_async_with_subprotocols, accept_hdr_async, accept_hdr_async_with_limits,
+    accept_hdr_async_with_request_headers, client_async, client_async_with_max_header_size,
+    set_websocket_key, HandshakeLimits,
+};
+use tungstenite::{
+    client::IntoClientRequest,
+    error::{Error, ProtocolError},
+    handshake::server::{ErrorResponse, NoCallback, Request, Response},
+    Message,
+};
 
 #[tokio::test]
 async fn handshakes() {
@@ -23,3 +40,256 @@ async fn handshakes() {
     let url = url::Url::parse("ws://localhost:12345/").unwrap();
     let _stream = client_async(url, tcp).await.expect("Client failed to connect");
 }
+
+#[tokio::test]
+async fn no_subprotocol() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, server) =
+        tokio::join!(client_async("ws://localhost/", client), accept_async(server));
+    let (client, _) = client.expect("Client failed to connect");
+    let server = server.expect("Failed to handshake with connection");
+    assert_eq!(client.selected_subprotocol(), None);
+    assert_eq!(server.selected_subprotocol(), None);
+}
+
+#[tokio::test]
+async fn selected_subprotocol() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut request = "ws://localhost/".into_client_request().unwrap();
+    request.headers_mut().insert("Sec-WebSocket-Protocol", "json, msgpack".parse().unwrap());
+    let callback = |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        assert_eq!(request.headers()["Sec-WebSocket-Protocol"], "json, msgpack");
+        response.headers_mut().insert("Sec-WebSocket-Protocol", "msgpack".parse().unwrap());
+        Ok(response)
+    };
+
+    let (client, server) =
+        tokio::join!(client_async(request, client), accept_hdr_async(server, callback));
+    let (client, response) = client.expect("Client failed to connect");
+    let server = server.expect("Failed to handshake with connection");
+    assert_eq!(response.headers()["Sec-WebSocket-Protocol"], "msgpack");
+    assert_eq!(client.selected_subprotocol(), Some("msgpack"));
+    assert_eq!(server.selected_subprotocol(), Some("msgpack"));
+}
+
+#[tokio::test]
+async fn rejected_upgrade_body() {
+    const BODY: &str = r#"{"error":"invalid api key"}"#;
+
+    let (client, mut server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(server.read_u8().await.unwrap());
+        }
+        let response = format!(
+            "HTTP/1.1 403 Forbidden\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            BODY.len(),
+            BODY
+        );
+        server.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    match client_async("ws://localhost/", client).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), 403);
+            assert_eq!(response.headers()["Content-Type"], "application/json");
+            assert_eq!(response.body().as_deref(), Some(BODY.as_bytes()));
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn request_headers() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut request = "ws://localhost/".into_client_request().unwrap();
+    request.headers_mut().insert("X-Api-Key", "secret".parse().unwrap());
+
+    let (client, server) = tokio::join!(
+        client_async(request, client),
+        accept_hdr_async_with_request_headers(server, NoCallback, None)
+    );
+    client.expect("Client failed to connect");
+    let server = server.expect("Failed to handshake with connection");
+    let headers = server.request_headers().expect("No request headers");
+    assert_eq!(headers["X-Api-Key"], "secret");
+
+    // Not kept unless asked for.
+    let (client, server) = tokio::io::duplex(1024);
+    let (_, server) = tokio::join!(client_async("ws://localhost/", client), accept_async(server));
+    assert!(server.unwrap().request_headers().is_none());
+}
+
+#[tokio::test]
+async fn max_header_size() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, server) = tokio::join!(
+        client_async_with_max_header_size("ws://localhost/", client, None, 1024),
+        accept_async(server)
+    );
+    client.expect("Client failed to connect");
+    server.expect("Failed to handshake with connection");
+
+    let (client, mut server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(server.read_u8().await.unwrap());
+        }
+        let mut response = String::from("HTTP/1.1 101 Switching Protocols\r\n");
+        for i in 0..64 {
+            response.push_str(&format!("X-Padding-{}: {}\r\n", i, "x".repeat(40)));
+        }
+        let _ = server.write_all(response.as_bytes()).await;
+    });
+
+    match client_async_with_max_header_size("ws://localhost/", client, None, 1024).await {
+        Err(Error::Protocol(ProtocolError::HttparseError(_))) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn fixed_websocket_key() {
+    // The example of RFC 6455, section 1.3.
+    let mut request = "ws://localhost/".into_client_request().unwrap();
+    set_websocket_key(&mut request, "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, server) = tokio::join!(client_async(request, client), accept_async(server));
+    let (_, response) = client.expect("Client failed to connect");
+    server.expect("Failed to handshake with connection");
+    assert_eq!(response.headers()["Sec-WebSocket-Accept"], "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+    let mut request = "ws://localhost/".into_client_request().unwrap();
+    let key = request.headers()["Sec-WebSocket-Key"].clone();
+    for invalid in ["", "not base64!", "dGhlIHNhbXBsZQ=="] {
+        assert!(set_websocket_key(&mut request, invalid).is_err(), "{invalid}");
+    }
+    assert_eq!(request.headers()["Sec-WebSocket-Key"], key);
+}
+
+/// A server answering the upgrade request of `fixed_websocket_key()` and sending a text
+/// message of `len` bytes, in a single write.
+async fn pipelining_server(mut server: tokio::io::DuplexStream, len: usize) {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(server.read_u8().await.unwrap());
+    }
+    let mut data = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"
+        .to_vec();
+    data.extend_from_slice(&[0x81, 126]);
+    data.extend_from_slice(&(len as u16).to_be_bytes());
+    data.extend(std::iter::repeat(b'x').take(len));
+    server.write_all(&data).await.unwrap();
+    // Keep the connection open until the client is done.
+    let _ = server.read_u8().await;
+}
+
+#[tokio::test]
+async fn frame_pipelined_with_response() {
+    for max_header_size in [None, Some(256)] {
+        let mut request = "ws://localhost/".into_client_request().unwrap();
+        set_websocket_key(&mut request, "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(pipelining_server(server, 1000));
+
+        let (mut stream, _) = match max_header_size {
+            Some(max) => client_async_with_max_header_size(request, client, None, max).await,
+            None => client_async(request, client).await,
+        }
+        .expect("Client failed to connect");
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(message, Message::text("x".repeat(1000)));
+    }
+}
+
+#[cfg(feature = "time")]
+#[tokio::test]
+async fn slow_handshake_request() {
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        // Dribble the request, one byte at a time.
+        for byte in b"GET / HTTP/1.1\r\nHost: localhost\r\n".iter() {
+            if client.write_u8(*byte).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+
+    let timeout = Duration::from_millis(200);
+    let limits = HandshakeLimits { timeout: Some(timeout), ..Default::default() };
+    let start = Instant::now();
+    match accept_hdr_async_with_limits(server, NoCallback, None, limits).await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < timeout * 3);
+}
+
+#[tokio::test]
+async fn large_handshake_request() {
+    let limits = HandshakeLimits { max_header_size: Some(1024), ..Default::default() };
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, server) = tokio::join!(
+        client_async("ws://localhost/", client),
+        accept_hdr_async_with_limits(server, NoCallback, None, limits)
+    );
+    client.expect("Client failed to connect");
+    server.expect("Failed to handshake with connection");
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let mut request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+        for i in 0..64 {
+            request.push_str(&format!("X-Padding-{}: {}\r\n", i, "x".repeat(40)));
+        }
+        let _ = client.write_all(request.as_bytes()).await;
+        let mut rest = Vec::new();
+        let _ = client.read_to_end(&mut rest).await;
+    });
+
+    match accept_hdr_async_with_limits(server, NoCallback, None, limits).await {
+        Err(Error::Protocol(ProtocolError::HttparseError(_))) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Connect offering `offered` to a server supporting `supported`.
+async fn negotiate(
+    offered: &str,
+    supported: &[&str],
+    required: bool,
+) -> (Result<Option<String>, Error>, Result<Option<String>, Error>) {
+    let mut request = "ws://localhost/".into_client_request().unwrap();
+    request.headers_mut().insert("Sec-WebSocket-Protocol", offered.parse().unwrap());
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, server) = tokio::join!(
+        client_async(request, client),
+        accept_async_with_subprotocols(server, supported, required)
+    );
+    (
+        client.map(|(stream, _)| stream.selected_subprotocol().map(str::to_owned)),
+        server.map(|stream| stream.selected_subprotocol().map(str::to_owned)),
+    )
+}
+
+#[tokio::test]
+async fn subprotocol_negotiation() {
+    // The preference of the server wins.
+    let (client, server) = negotiate("msgpack, json", &["json", "msgpack"], true).await;
+    assert_eq!(client.unwrap().as_deref(), Some("json"));
+    assert_eq!(server.unwrap().as_deref(), Some("json"));
+
+    let (client, server) = negotiate("xml", &["json", "msgpack"], false).await;
+    assert_eq!(client.unwrap(), None);
+    assert_eq!(server.unwrap(), None);
+
+    match negotiate("xml", &["json", "msgpack"], true).await {
+        (Err(Error::Http(response)), Err(_)) => assert_eq!(response.status(), 400),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,177 @@
+#![cfg(feature = "connect")]
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::{
+    accept_async, connect_async_with_config_with_http_proxy, ClientBuilder, ProxyKind,
+};
+use tungstenite::{Error, Message};
+
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.expect("Failed to read request head"));
+    }
+    String::from_utf8(head).unwrap()
+}
+
+/// A proxy that answers every `CONNECT` with `response` and, if it is successful,
+/// pipes the client to the requested destination.
+async fn run_proxy(listener: TcpListener, response: &'static str) -> String {
+    let (mut client, _) = listener.accept().await.expect("No connections to accept");
+    let head = read_head(&mut client).await;
+    client.write_all(response.as_bytes()).await.unwrap();
+
+    if response.starts_with("HTTP/1.1 200") {
+        let target = head.split(' ').nth(1).unwrap().to_owned();
+        tokio::spawn(async move {
+            let mut server = TcpStream::connect(target).await.expect("Failed to connect");
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+        });
+    }
+    head
+}
+
+#[tokio::test]
+async fn tunnel_through_proxy() {
+    let _ = env_logger::try_init();
+
+    let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = server.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while let Some(Ok(message)) = stream.next().await {
+            if message.is_text() {
+                stream.send(message).await.unwrap();
+            }
+        }
+    });
+
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_uri = format!("http://user:secret@{}", proxy.local_addr().unwrap()).parse().unwrap();
+    let proxy = tokio::spawn(run_proxy(
+        proxy,
+        "HTTP/1.1 200 Connection established\r\nProxy-Agent: mock\r\nX-Extra: 1\r\n\r\n",
+    ));
+
+    let url = format!("ws://{server_addr}/");
+    let (mut stream, _) = connect_async_with_config_with_http_proxy(url, None, false, proxy_uri)
+        .await
+        .expect("Failed to connect through the proxy");
+
+    let head = proxy.await.unwrap();
+    assert!(head.starts_with(&format!("CONNECT {server_addr} HTTP/1.1\r\n")));
+    // base64("user:secret")
+    assert!(head.contains("Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n"));
+
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hello"));
+}
+
+#[tokio::test]
+async fn proxy_rejects_tunnel() {
+    let _ = env_logger::try_init();
+
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_uri = format!("http://{}", proxy.local_addr().unwrap()).parse().unwrap();
+    let proxy = tokio::spawn(run_proxy(
+        proxy,
+        "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n",
+    ));
+
+    let result =
+        connect_async_with_config_with_http_proxy("ws://example.com/", None, false, proxy_uri)
+            .await;
+    match result {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 407),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let head = proxy.await.unwrap();
+    assert!(head.starts_with("CONNECT example.com:80 HTTP/1.1\r\n"));
+    assert!(!head.contains("Proxy-Authorization"));
+}
+
+#[tokio::test]
+async fn ipv6_destination() {
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_uri = format!("http://{}", proxy.local_addr().unwrap()).parse().unwrap();
+    let proxy = tokio::spawn(run_proxy(proxy, "HTTP/1.1 403 Forbidden\r\n\r\n"));
+
+    let result = connect_async_with_config_with_http_proxy(
+        "ws://[2001:db8::1]:9000/",
+        None,
+        false,
+        proxy_uri,
+    )
+    .await;
+    match result {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 403),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    // The address is sent as such, in brackets, not resolved or mistaken for a name.
+    let head = proxy.await.unwrap();
+    assert!(head.starts_with("CONNECT [2001:db8::1]:9000 HTTP/1.1\r\nHost: [2001:db8::1]:9000\r\n"));
+}
+
+#[tokio::test]
+async fn info_through_proxy() {
+    let _ = env_logger::try_init();
+
+    let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = server.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while stream.next().await.is_some() {}
+    });
+
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    let proxy_uri = format!("http://{proxy_addr}").parse().unwrap();
+    tokio::spawn(run_proxy(proxy, "HTTP/1.1 200 Connection established\r\n\r\n"));
+
+    let (_stream, _, info) = ClientBuilder::new(format!("ws://{server_addr}/"))
+        .http_proxy(proxy_uri)
+        .connect_with_info()
+        .await
+        .expect("Failed to connect through the proxy");
+    assert_eq!(info.peer_addr, proxy_addr);
+    assert_eq!(info.tunneled, Some(server_addr.to_string()));
+    let proxy = info.proxy.expect("No proxy info");
+    assert_eq!(proxy.kind, ProxyKind::HttpConnect);
+    assert_eq!(proxy.addr, proxy_addr.to_string());
+    assert!(!info.tls);
+}
+
+#[tokio::test]
+async fn proxy_never_answers() {
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut client, _) = proxy.accept().await.expect("No connections to accept");
+        read_head(&mut client).await;
+        // Keep the connection open without answering.
+        let _ = client.read_u8().await;
+    });
+
+    let result = ClientBuilder::new("ws://127.0.0.1:1/")
+        .http_proxy(format!("http://{proxy_addr}").parse().unwrap())
+        .proxy_response_timeout(Duration::from_millis(100))
+        .connect()
+        .await;
+    match result {
+        Err(Error::Io(e)) => {
+            assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+            assert!(e.to_string().contains(&proxy_addr.to_string()), "{e}");
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,186 @@
+#![cfg(feature = "time")]
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{io::AsyncReadExt, time::Instant};
+use tokio_tungstenite::{IdleTimeout, KeepAlive, WebSocketStream};
+use tungstenite::{protocol::Role, Error, Message};
+
+const KEEPALIVE: KeepAlive =
+    KeepAlive { interval: Duration::from_millis(100), timeout: Duration::from_millis(100) };
+
+#[tokio::test]
+async fn keepalive_times_out() {
+    let (client, mut server) = tokio::io::duplex(1024);
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_keepalive(KEEPALIVE);
+
+    let start = Instant::now();
+    match stream.next().await {
+        Some(Err(Error::Io(e))) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("unexpected item: {:?}", other),
+    }
+    assert!(start.elapsed() >= KEEPALIVE.interval + KEEPALIVE.timeout);
+    assert!(stream.next().await.is_none());
+
+    // The ping (FIN bit set, opcode 0x9) has been sent to the peer.
+    assert_eq!(server.read_u8().await.unwrap(), 0x89);
+}
+
+#[tokio::test]
+async fn keepalive_answered() {
+    let (client, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        // Pongs are sent automatically while reading.
+        while server.next().await.is_some() {}
+    });
+
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_keepalive(KEEPALIVE);
+    for _ in 0..3 {
+        let message = stream.next().await.unwrap().expect("Connection considered dead");
+        assert_eq!(message, Message::Pong(Vec::new()));
+    }
+}
+
+#[tokio::test]
+async fn idle_connection_closed() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (close_tx, close_rx) = futures_channel::oneshot::channel();
+    tokio::spawn(async move {
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        while let Some(Ok(message)) = server.next().await {
+            if let Message::Close(frame) = message {
+                close_tx.send(frame).unwrap();
+                break;
+            }
+        }
+        // Keep reading so that the close reply is flushed.
+        while server.next().await.is_some() {}
+    });
+
+    let idle = IdleTimeout::new(Duration::from_millis(100));
+    let mut stream =
+        WebSocketStream::from_raw_socket(client, Role::Client, None).await.with_idle_timeout(idle);
+    let start = Instant::now();
+    assert!(stream.next().await.is_none());
+    assert!(start.elapsed() >= idle.timeout);
+
+    let frame = close_rx.await.unwrap().expect("No close frame");
+    assert_eq!(u16::from(frame.code), 1000);
+}
+
+#[tokio::test]
+async fn idle_close_abandoned() {
+    // The peer never answers the close frame.
+    let (client, mut server) = tokio::io::duplex(1024);
+    let idle = IdleTimeout {
+        grace: Duration::from_millis(50),
+        ..IdleTimeout::new(Duration::from_millis(100))
+    };
+    let mut stream =
+        WebSocketStream::from_raw_socket(client, Role::Client, None).await.with_idle_timeout(idle);
+
+    let start = Instant::now();
+    assert!(stream.next().await.is_none());
+    assert!(start.elapsed() >= idle.timeout + idle.grace);
+
+    // The close frame (FIN bit set, opcode 0x8) has been sent to the peer.
+    assert_eq!(server.read_u8().await.unwrap(), 0x88);
+}
+
+#[tokio::test]
+async fn write_times_out() {
+    // The peer never reads, so the message cannot be written completely.
+    let (client, _server) = tokio::io::duplex(256);
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_write_timeout(Duration::from_millis(100));
+
+    match stream.send(Message::Binary(vec![0; 8192])).await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn slow_write_progresses() {
+    let (client, mut server) = tokio::io::duplex(256);
+    tokio::spawn(async move {
+        // Read slowly: it takes much longer than the timeout to read the whole message.
+        let mut buf = [0; 256];
+        while server.read(&mut buf).await.map_or(false, |n| n > 0) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    });
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_write_timeout(Duration::from_millis(200));
+
+    let start = Instant::now();
+    stream.send(Message::Binary(vec![0; 8192])).await.unwrap();
+    assert!(start.elapsed() > Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn close_not_answered() {
+    // The peer never reads, so it never answers the close frame.
+    let (client, _server) = tokio::io::duplex(1024);
+    let timeout = Duration::from_millis(100);
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_close_timeout(timeout);
+
+    let start = Instant::now();
+    stream.close(None).await.unwrap();
+    assert!(stream.next().await.is_none());
+    assert!(start.elapsed() >= timeout);
+}
+
+#[tokio::test]
+async fn close_not_completed() {
+    // The peer closes but never closes the connection after the closing handshake.
+    let (client, server) = tokio::io::duplex(1024);
+    let timeout = Duration::from_millis(100);
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_close_timeout(timeout);
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    server.close(None).await.unwrap();
+
+    let start = Instant::now();
+    assert!(matches!(stream.next().await, Some(Ok(Message::Close(None)))));
+    assert!(stream.next().await.is_none());
+    assert!(start.elapsed() >= timeout);
+    drop(server);
+}
+
+#[tokio::test]
+async fn read_timeout_recovers() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    let timeout = Duration::from_millis(100);
+    let mut stream = WebSocketStream::from_raw_socket(client, Role::Client, None)
+        .await
+        .with_read_timeout(timeout);
+
+    for _ in 0..2 {
+        let start = Instant::now();
+        match stream.next().await {
+            Some(Err(Error::Io(e))) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert!(start.elapsed() >= timeout);
+    }
+
+    // The connection is still usable.
+    server.send(Message::text("late")).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("late"));
+    stream.send(Message::text("still here")).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("still here"));
+}
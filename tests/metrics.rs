@@ -0,0 +1,60 @@
+#![cfg(all(feature = "metrics", feature = "connect"))]
+
+use futures_util::{SinkExt, StreamExt};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, connect_async};
+use tungstenite::Message;
+
+#[tokio::test]
+async fn metrics_recorded() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while let Some(Ok(message)) = stream.next().await {
+            if message.is_text() {
+                stream.send(message).await.unwrap();
+            }
+        }
+    });
+
+    let (mut stream, _) = connect_async(format!("ws://{addr}/")).await.expect("Failed to connect");
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hello"));
+    stream.close(None).await.unwrap();
+    while stream.next().await.is_some() {}
+
+    let metrics = snapshotter.snapshot().into_vec();
+    let value = |name: &str, kind: Option<&str>| {
+        metrics
+            .iter()
+            .filter(|(key, ..)| key.key().name() == name)
+            .filter(|(key, ..)| {
+                kind.map_or(true, |kind| key.key().labels().any(|label| label.value() == kind))
+            })
+            .map(|(.., value)| match value {
+                DebugValue::Counter(value) => *value,
+                DebugValue::Histogram(values) => values.len() as u64,
+                DebugValue::Gauge(_) => unreachable!(),
+            })
+            .sum::<u64>()
+    };
+
+    // Both the client and the server.
+    assert_eq!(value("ws_connections_opened_total", None), 2);
+    // The text message and the close frame, both ways.
+    assert_eq!(value("ws_messages_sent_total", Some("data")), 2);
+    assert_eq!(value("ws_messages_received_total", Some("data")), 2);
+    assert_eq!(value("ws_bytes_sent_total", Some("data")), 10);
+    assert_eq!(value("ws_messages_sent_total", Some("close")), 1);
+    assert_eq!(value("ws_messages_received_total", Some("close")), 2);
+    assert_eq!(value("ws_connect_duration_seconds", Some("tcp_connect")), 1);
+    assert_eq!(value("ws_connect_duration_seconds", Some("websocket_handshake")), 1);
+    assert_eq!(value("ws_errors_total", None), 0);
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "rustls-pinning")]
+
+use std::sync::Arc;
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{accept_async, client_async_tls_with_config, Connector};
+use tungstenite::Error;
+
+/// A self-signed certificate for `localhost`.
+const CERT: &[u8] = include_bytes!("certs/localhost.der");
+const KEY: &[u8] = include_bytes!("certs/localhost.key.der");
+/// SHA-256 fingerprint of `CERT`.
+const FINGERPRINT: [u8; 32] = [
+    0x87, 0x0f, 0xec, 0xa5, 0x86, 0xc8, 0x04, 0xa8, 0x7e, 0xb9, 0xf2, 0x23, 0xc9, 0x43, 0x6e, 0x3b,
+    0x5f, 0xe3, 0x30, 0xce, 0x62, 0x9a, 0x97, 0x7b, 0xf2, 0xb5, 0xe7, 0x03, 0xfc, 0x50, 0x50, 0xaa,
+];
+
+async fn tls_server() -> std::net::SocketAddr {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((connection, _)) = listener.accept().await {
+            if let Ok(stream) = acceptor.accept(connection).await {
+                let _ = accept_async(stream).await;
+            }
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn pinned_certificate_accepted() {
+    let addr = tls_server().await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let connector = Connector::Pinned(vec![FINGERPRINT]);
+    client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector))
+        .await
+        .expect("Client failed to connect");
+}
+
+#[tokio::test]
+async fn unpinned_certificate_rejected() {
+    let addr = tls_server().await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let connector = Connector::Pinned(vec![[0; 32]]);
+    match client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector)).await {
+        Err(Error::Tls(_)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
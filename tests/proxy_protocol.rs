@@ -0,0 +1,62 @@
+#![cfg(feature = "connect")]
+
+use tokio::{io::AsyncReadExt, net::TcpListener};
+use tokio_tungstenite::{
+    accept_async, connect_async_with_proxy_protocol, ClientBuilder, ProxyHeader,
+    ProxyProtocolVersion,
+};
+
+#[tokio::test]
+async fn proxy_protocol_v1() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut connection, peer) = listener.accept().await.expect("No connections to accept");
+        let mut preamble = Vec::new();
+        while !preamble.ends_with(b"\r\n") {
+            preamble.push(connection.read_u8().await.expect("Failed to read the preamble"));
+        }
+        let _stream = accept_async(connection).await.expect("Failed to handshake");
+        (String::from_utf8(preamble).unwrap(), peer)
+    });
+
+    let header = ProxyHeader::new(ProxyProtocolVersion::V1);
+    let (_stream, _) =
+        connect_async_with_proxy_protocol(format!("ws://{addr}/"), None, false, header)
+            .await
+            .expect("Failed to connect");
+    let (preamble, peer) = server.await.unwrap();
+    assert_eq!(
+        preamble,
+        format!("PROXY TCP4 127.0.0.1 127.0.0.1 {} {}\r\n", peer.port(), addr.port())
+    );
+}
+
+#[tokio::test]
+async fn proxy_protocol_v2() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut preamble = [0; 28];
+        connection.read_exact(&mut preamble).await.expect("Failed to read the preamble");
+        let _stream = accept_async(connection).await.expect("Failed to handshake");
+        preamble
+    });
+
+    let header = ProxyHeader {
+        source: Some("192.0.2.1:50000".parse().unwrap()),
+        destination: Some("198.51.100.2:443".parse().unwrap()),
+        ..ProxyHeader::new(ProxyProtocolVersion::V2)
+    };
+    let builder = ClientBuilder::new(format!("ws://{addr}/")).proxy_protocol(header);
+    let (_stream, _) = builder.connect().await.expect("Failed to connect");
+    let preamble = server.await.unwrap();
+    assert_eq!(&preamble[..12], b"\r\n\r\n\0\r\nQUIT\n");
+    assert_eq!(&preamble[12..16], [0x21, 0x11, 0, 12]);
+    assert_eq!(&preamble[16..], [192, 0, 2, 1, 198, 51, 100, 2, 0xc3, 0x50, 0x01, 0xbb]);
+}
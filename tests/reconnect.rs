@@ -0,0 +1,75 @@
+#![cfg(feature = "connect")]
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{
+    accept_async, Backoff, Reconnect, ReconnectEvent, ReconnectingStream, WhileDisconnected,
+};
+use tungstenite::{Error, Message};
+
+const BACKOFF: Backoff =
+    Backoff { base: Duration::from_millis(10), max: Duration::from_millis(100), jitter: 0.0 };
+
+#[tokio::test]
+async fn reconnect_after_close() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::text("one")).await.unwrap();
+        stream.close(None).await.unwrap();
+        while stream.next().await.is_some() {}
+
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::text("two")).await.unwrap();
+        stream.next().await.unwrap().unwrap()
+    });
+
+    let reconnect =
+        Reconnect { backoff: BACKOFF, while_disconnected: WhileDisconnected::Buffer(8) };
+    let (mut stream, _) = ReconnectingStream::connect(url, None, reconnect).await.unwrap();
+
+    match stream.next().await {
+        Some(Ok(ReconnectEvent::Message(message))) => assert_eq!(message, Message::text("one")),
+        other => panic!("unexpected item: {:?}", other),
+    }
+    match stream.next().await {
+        Some(Ok(ReconnectEvent::Reconnected(response))) => assert_eq!(response.status(), 101),
+        other => panic!("unexpected item: {:?}", other),
+    }
+    match stream.next().await {
+        Some(Ok(ReconnectEvent::Message(message))) => assert_eq!(message, Message::text("two")),
+        other => panic!("unexpected item: {:?}", other),
+    }
+
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(server.await.unwrap(), Message::text("hello"));
+}
+
+#[tokio::test]
+async fn reject_while_disconnected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        // Accept a single connection and close it, the next attempts are refused.
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.close(None).await.unwrap();
+        while stream.next().await.is_some() {}
+    });
+
+    let reconnect = Reconnect { backoff: BACKOFF, while_disconnected: WhileDisconnected::Reject };
+    let (mut stream, _) = ReconnectingStream::connect(url, None, reconnect).await.unwrap();
+
+    // Nothing but failing attempts to connect again.
+    assert!(tokio::time::timeout(Duration::from_millis(200), stream.next()).await.is_err());
+    assert!(!stream.is_connected());
+    match stream.send(Message::text("lost")).await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotConnected),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
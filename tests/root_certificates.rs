@@ -0,0 +1,73 @@
+#![cfg(feature = "__rustls-tls")]
+
+use std::sync::Arc;
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{accept_async, client_async_tls_with_config, Connector};
+use tungstenite::Error;
+
+/// A test certificate authority, and a certificate for `localhost` it signed.
+const CA: &[u8] = include_bytes!("certs/ca.pem");
+const CERT: &[u8] = include_bytes!("certs/ca-signed.der");
+const KEY: &[u8] = include_bytes!("certs/ca-signed.key.der");
+
+async fn tls_server() -> std::net::SocketAddr {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((connection, _)) = listener.accept().await {
+            if let Ok(stream) = acceptor.accept(connection).await {
+                let _ = accept_async(stream).await;
+            }
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn extra_root_accepted() {
+    let addr = tls_server().await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let connector = Connector::with_root_certificates(CA).unwrap();
+    client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector))
+        .await
+        .expect("Client failed to connect");
+}
+
+#[tokio::test]
+async fn unknown_root_rejected() {
+    let addr = tls_server().await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let result = client_async_tls_with_config("wss://localhost/", tcp, None, None).await;
+    assert!(result.is_err());
+}
+
+#[cfg(feature = "danger-accept-invalid-certs")]
+#[tokio::test]
+async fn verification_disabled() {
+    let addr = tls_server().await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let connector = Connector::danger_accept_invalid_certs().unwrap();
+    client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector))
+        .await
+        .expect("Client failed to connect");
+}
+
+#[test]
+fn malformed_pem() {
+    let truncated = &CA[..CA.len() / 2];
+    for pem in [b"not a certificate".as_slice(), truncated] {
+        match Connector::with_root_certificates(pem) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
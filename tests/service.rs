@@ -0,0 +1,23 @@
+#![cfg(feature = "tower")]
+
+use futures_util::StreamExt;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, ConnectService};
+use tower::ServiceExt;
+use tungstenite::client::IntoClientRequest;
+
+#[tokio::test]
+async fn connect_service() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while stream.next().await.is_some() {}
+    });
+
+    let request = format!("ws://{addr}/").into_client_request().unwrap();
+    let (_stream, response) =
+        ConnectService::new(None, true).oneshot(request).await.expect("Failed to connect");
+    assert_eq!(response.status(), 101);
+}
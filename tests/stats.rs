@@ -0,0 +1,40 @@
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::{
+    tungstenite::{protocol::Role, Message},
+    WebSocketStream,
+};
+
+#[tokio::test]
+async fn stats() {
+    let (client, server) = tokio::io::duplex(1024);
+    let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let mut client = client.with_stats();
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+    assert!(server.stats().is_none());
+
+    let stats = client.stats().unwrap();
+    assert_eq!(stats.messages_sent, 0);
+    assert!(stats.last_sent.is_none() && stats.last_received.is_none());
+
+    client.send(Message::Text("hello".into())).await.unwrap();
+    client.send(Message::Ping(vec![1, 2])).await.unwrap();
+    server.send(Message::Binary(vec![0; 10])).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Text("hello".into()));
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Ping(vec![1, 2]));
+    // Sends the pong.
+    server.flush().await.unwrap();
+
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Binary(vec![0; 10]));
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Pong(vec![1, 2]));
+
+    let stats = client.stats().unwrap();
+    assert_eq!(stats.messages_sent, 2);
+    assert_eq!(stats.bytes_sent, 7);
+    assert_eq!(stats.pings_sent, 1);
+    assert_eq!(stats.pongs_sent, 0);
+    assert_eq!(stats.messages_received, 2);
+    assert_eq!(stats.bytes_received, 12);
+    assert_eq!(stats.pings_received, 0);
+    assert_eq!(stats.pongs_received, 1);
+    assert!(stats.last_sent.unwrap() <= stats.last_received.unwrap());
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "connect")]
+
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpListener;
+use tokio_tungstenite::connect_async_with_timeout;
+use tungstenite::Error;
+
+#[tokio::test]
+async fn connect_to_unroutable_address() {
+    let _ = env_logger::try_init();
+
+    let timeout = Duration::from_millis(500);
+    let start = Instant::now();
+    // Nothing answers on this address, depending on the network it is either
+    // black-holed or rejected right away.
+    let result = connect_async_with_timeout("ws://10.255.255.1:81/", None, false, timeout).await;
+    assert!(result.is_err());
+    assert!(start.elapsed() < timeout * 3);
+}
+
+#[tokio::test]
+async fn handshake_timeout() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        // Accept the connection, but never reply to the handshake.
+        let (_connection, _) = listener.accept().await.expect("No connections to accept");
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+
+    let timeout = Duration::from_millis(200);
+    let start = Instant::now();
+    let result = connect_async_with_timeout(format!("ws://{addr}/"), None, false, timeout).await;
+    match result {
+        Err(Error::Io(e)) => {
+            assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+            assert_eq!(e.to_string(), "WebSocket handshake timed out");
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < timeout * 3);
+}
+
+#[cfg(feature = "cancel")]
+#[tokio::test]
+async fn cancel_handshake() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        // Accept the connection, but never reply to the handshake.
+        let (_connection, _) = listener.accept().await.expect("No connections to accept");
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+
+    let token = tokio_util::sync::CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        cancel.cancel();
+    });
+
+    let start = Instant::now();
+    let result =
+        tokio_tungstenite::connect_async_with_cancel(format!("ws://{addr}/"), None, false, token)
+            .await;
+    match result {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Interrupted),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(feature = "cancel")]
+#[tokio::test]
+async fn cancelled_before_connecting() {
+    let token = tokio_util::sync::CancellationToken::new();
+    token.cancel();
+    let result =
+        tokio_tungstenite::connect_async_with_cancel("ws://127.0.0.1:1/", None, false, token).await;
+    match result {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Interrupted),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+#[tokio::test]
+async fn established_connection_handshake_timeout() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        // Accept the connection, but never reply to the handshake.
+        let (_connection, _) = listener.accept().await.expect("No connections to accept");
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let timeout = Duration::from_millis(200);
+    let start = Instant::now();
+    let url = format!("ws://{addr}/");
+    let result =
+        tokio_tungstenite::client_async_tls_with_timeout(url, tcp, None, None, timeout).await;
+    match result {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < timeout * 3);
+}
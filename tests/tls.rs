@@ -0,0 +1,215 @@
+#![cfg(feature = "__rustls-tls")]
+
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    server::{ServerSessionMemoryCache, StoresServerSessions},
+    Certificate, ClientConfig, Error as TlsError, PrivateKey, ServerConfig, ServerName,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::{
+    accept_async, client_async_tls_with_config, client_async_with_config, Connector, MaybeTlsStream,
+};
+
+/// A self-signed certificate for `localhost`.
+const CERT: &[u8] = include_bytes!("certs/localhost.der");
+const KEY: &[u8] = include_bytes!("certs/localhost.key.der");
+
+/// Trusts any certificate, certificates are tested in `pinning.rs`.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+async fn tls_server(alpn: &[&[u8]]) -> std::net::SocketAddr {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+        .unwrap();
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    tls_server_with_config(config).await
+}
+
+async fn tls_server_with_config(config: ServerConfig) -> std::net::SocketAddr {
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((connection, _)) = listener.accept().await {
+            if let Ok(stream) = acceptor.accept(connection).await {
+                let _ = accept_async(stream).await;
+            }
+        }
+    });
+    addr
+}
+
+/// Counts the sessions resumed by the server.
+struct CountingSessions {
+    sessions: Arc<ServerSessionMemoryCache>,
+    resumed: AtomicUsize,
+}
+
+impl CountingSessions {
+    fn count(&self, session: Option<Vec<u8>>) -> Option<Vec<u8>> {
+        if session.is_some() {
+            self.resumed.fetch_add(1, Ordering::SeqCst);
+        }
+        session
+    }
+}
+
+impl StoresServerSessions for CountingSessions {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.sessions.put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.count(self.sessions.get(key))
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.count(self.sessions.take(key))
+    }
+
+    fn can_cache(&self) -> bool {
+        self.sessions.can_cache()
+    }
+}
+
+fn client_config() -> Arc<ClientConfig> {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(NoVerification))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Arc::new(config)
+}
+
+fn connector() -> Connector {
+    Connector::Rustls(client_config())
+}
+
+#[tokio::test]
+async fn negotiated_alpn_protocol() {
+    let addr = tls_server(&[b"http/1.1"]).await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) =
+        client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector()))
+            .await
+            .expect("Client failed to connect");
+    assert_eq!(stream.get_ref().alpn_protocol().as_deref(), Some(&b"http/1.1"[..]));
+    assert!(stream.is_tls());
+}
+
+#[tokio::test]
+async fn negotiated_version_and_cipher_suite() {
+    let addr = tls_server(&[]).await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) =
+        client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector()))
+            .await
+            .expect("Client failed to connect");
+    assert_eq!(stream.get_ref().tls_version().as_deref(), Some("TLSv1.3"));
+    let cipher_suite = stream.get_ref().cipher_suite().unwrap();
+    assert!(cipher_suite.starts_with("TLS13_"), "{cipher_suite}");
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, _) = tokio::join!(
+        client_async_tls_with_config("ws://localhost/", client, None, None),
+        accept_async(server)
+    );
+    let (stream, _) = client.expect("Client failed to connect");
+    assert_eq!(stream.get_ref().tls_version(), None);
+    assert_eq!(stream.get_ref().cipher_suite(), None);
+}
+
+#[tokio::test]
+async fn no_alpn_protocol() {
+    let addr = tls_server(&[]).await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) =
+        client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector()))
+            .await
+            .expect("Client failed to connect");
+    assert_eq!(stream.get_ref().alpn_protocol(), None);
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (client, _) = tokio::join!(
+        client_async_tls_with_config("ws://localhost/", client, None, None),
+        accept_async(server)
+    );
+    let (stream, _) = client.expect("Client failed to connect");
+    assert_eq!(stream.get_ref().alpn_protocol(), None);
+    assert!(!stream.is_tls());
+}
+
+#[tokio::test]
+async fn custom_tls_stream() {
+    let addr = tls_server(&[]).await;
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let tls = TlsConnector::from(client_config()).connect(server_name, tcp).await.unwrap();
+
+    let (stream, response) =
+        client_async_with_config("wss://localhost/", MaybeTlsStream::Rustls(tls), None)
+            .await
+            .expect("Client failed to connect");
+    assert_eq!(response.status(), 101);
+    assert!(stream.is_tls());
+}
+
+#[tokio::test]
+async fn shared_config_resumes_sessions() {
+    let sessions = Arc::new(CountingSessions {
+        sessions: ServerSessionMemoryCache::new(16),
+        resumed: AtomicUsize::new(0),
+    });
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![Certificate(CERT.to_vec())], PrivateKey(KEY.to_vec()))
+        .unwrap();
+    config.session_storage = sessions.clone();
+    let addr = tls_server_with_config(config).await;
+    let connect = |connector: Connector| async move {
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        client_async_tls_with_config("wss://localhost/", tcp, None, Some(connector))
+            .await
+            .expect("Client failed to connect");
+    };
+
+    // The second connection resumes the session of the first one.
+    let connector = connector();
+    connect(connector.clone()).await;
+    assert_eq!(sessions.resumed.load(Ordering::SeqCst), 0);
+    connect(connector).await;
+    assert_eq!(sessions.resumed.load(Ordering::SeqCst), 1);
+
+    // Not without sharing the configuration, and its cache of sessions.
+    connect(connector()).await;
+    connect(connector()).await;
+    assert_eq!(sessions.resumed.load(Ordering::SeqCst), 1);
+}
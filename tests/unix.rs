@@ -0,0 +1,72 @@
+#![cfg(all(unix, feature = "unix"))]
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::UnixListener;
+use tokio_tungstenite::{accept_async, accept_async_unix, connect_async_unix};
+use tungstenite::{Error, Message};
+
+fn socket_path(name: &str) -> std::path::PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("tokio-tungstenite-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn unix_client() {
+    let _ = env_logger::try_init();
+
+    let path = socket_path("client.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection).await.expect("Failed to handshake");
+        while let Some(Ok(message)) = stream.next().await {
+            if message.is_text() {
+                stream.send(message).await.unwrap();
+            }
+        }
+    });
+
+    let url = format!("ws+unix://{}:/chat", path.display());
+    let (mut stream, _) = connect_async_unix(&url, None).await.expect("Failed to connect");
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hello"));
+    stream.close(None).await.unwrap();
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn unix_server() {
+    let _ = env_logger::try_init();
+
+    let path = socket_path("server.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        let mut stream = accept_async_unix(&listener, None).await.expect("Failed to accept");
+        while let Some(Ok(message)) = stream.next().await {
+            if message.is_text() {
+                stream.send(message).await.unwrap();
+            }
+        }
+    });
+
+    let url = format!("ws+unix://{}", path.display());
+    let (mut stream, _) = connect_async_unix(&url, None).await.expect("Failed to connect");
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hello"));
+    stream.close(None).await.unwrap();
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn missing_socket() {
+    let path = socket_path("missing.sock");
+    let url = format!("ws+unix://{}", path.display());
+    match connect_async_unix(&url, None).await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
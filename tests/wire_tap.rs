@@ -0,0 +1,34 @@
+#![cfg(feature = "handshake")]
+
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::{accept_async, client_async, TapDirection, WireTap};
+use tungstenite::Message;
+
+#[tokio::test]
+async fn wire_tap() {
+    let (client, server) = tokio::io::duplex(1024);
+    let inbound = Arc::new(Mutex::new(Vec::new()));
+    let outbound = Arc::new(Mutex::new(Vec::new()));
+    let (tapped_in, tapped_out) = (inbound.clone(), outbound.clone());
+    let client = WireTap::new(client, move |direction, bytes: &[u8]| match direction {
+        TapDirection::Inbound => tapped_in.lock().unwrap().extend_from_slice(bytes),
+        TapDirection::Outbound => tapped_out.lock().unwrap().extend_from_slice(bytes),
+    });
+
+    let (client, server) =
+        tokio::join!(client_async("ws://localhost/", client), accept_async(server));
+    let (mut client, _) = client.expect("Client failed to connect");
+    let mut server = server.expect("Failed to handshake with connection");
+    client.send(Message::text("hello")).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::text("hello"));
+
+    let outbound = outbound.lock().unwrap();
+    assert!(outbound.starts_with(b"GET / HTTP/1.1\r\n"));
+    let head_end = outbound.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    // A masked text frame with a 5 bytes payload.
+    assert_eq!(&outbound[head_end..head_end + 2], [0x81, 0x85]);
+    assert_eq!(outbound.len(), head_end + 2 + 4 + 5);
+    assert!(inbound.lock().unwrap().starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+}
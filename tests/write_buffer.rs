@@ -0,0 +1,182 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio_tungstenite::{
+    tungstenite::{
+        protocol::{Role, WebSocketConfig},
+        Error, Message,
+    },
+    WebSocketStream,
+};
+
+/// A stream discarding everything written to it, counting the calls to `poll_write`.
+struct CountingStream {
+    writes: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Feed `count` small messages, flush, and return the number of writes to the stream.
+async fn writes_for(count: usize, config: WebSocketConfig) -> usize {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let stream = CountingStream { writes: writes.clone() };
+    let mut stream = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config)).await;
+    for i in 0..count {
+        stream.feed(Message::text(format!("message {i}"))).await.unwrap();
+    }
+    stream.flush().await.unwrap();
+    writes.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn small_messages_coalesced() {
+    assert_eq!(writes_for(100, WebSocketConfig::default()).await, 1);
+
+    let config = WebSocketConfig { write_buffer_size: 0, ..Default::default() };
+    assert!(writes_for(100, config).await >= 100);
+}
+
+#[tokio::test]
+async fn full_write_buffer_waits() {
+    // The peer does not read until told to, so the messages pile up in the write buffer.
+    let (client, mut server) = tokio::io::duplex(64);
+    let config =
+        WebSocketConfig { write_buffer_size: 0, max_write_buffer_size: 1024, ..Default::default() };
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, Some(config)).await;
+
+    let sending = tokio::spawn(async move {
+        for _ in 0..50 {
+            client.feed(Message::binary(vec![0; 100])).await.unwrap();
+        }
+        client.flush().await.unwrap();
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!sending.is_finished());
+
+    let mut received = 0;
+    let mut buf = [0; 4096];
+    // 50 frames of a 100 bytes payload, a 2 bytes header and a 4 bytes mask.
+    while received < 50 * 106 {
+        received += server.read(&mut buf).await.unwrap();
+    }
+    sending.await.unwrap();
+}
+
+#[tokio::test]
+async fn flush_pending() {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let stream = CountingStream { writes: writes.clone() };
+    let mut stream = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    assert!(!stream.flush_pending().await.unwrap());
+
+    for i in 0..10 {
+        stream.feed(Message::text(format!("message {i}"))).await.unwrap();
+    }
+    assert_eq!(writes.load(Ordering::SeqCst), 0);
+    assert!(stream.flush_pending().await.unwrap());
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+    assert!(!stream.flush_pending().await.unwrap());
+}
+
+#[tokio::test]
+async fn pending_write_bytes() {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let stream = CountingStream { writes: writes.clone() };
+    let mut stream = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+    assert_eq!(stream.pending_write_bytes(), 0);
+
+    for i in 1..=10 {
+        stream.feed(Message::binary(vec![0; 100])).await.unwrap();
+        assert_eq!(stream.pending_write_bytes(), i * 100);
+    }
+    stream.flush().await.unwrap();
+    assert_eq!(stream.pending_write_bytes(), 0);
+}
+
+#[tokio::test]
+async fn fragmented_messages() {
+    // Server frames are not masked, which makes them easy to check.
+    let (server, mut client) = tokio::io::duplex(1024);
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None)
+        .await
+        .with_max_outgoing_frame_size(10);
+    server.send(Message::binary(vec![7; 25])).await.unwrap();
+    server.send(Message::text("short")).await.unwrap();
+
+    let mut wire = vec![0; 3 * 2 + 25 + 2 + 5];
+    client.read_exact(&mut wire).await.unwrap();
+    // Binary without FIN, then a continuation without FIN and the last one with FIN.
+    assert_eq!(&wire[..2], [0x02, 10]);
+    assert_eq!(&wire[12..14], [0x00, 10]);
+    assert_eq!(&wire[24..26], [0x80, 5]);
+    assert_eq!(&wire[31..33], [0x81, 5]);
+
+    // The peer gets the messages back in one piece.
+    let (server, client) = tokio::io::duplex(1024);
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None)
+        .await
+        .with_max_outgoing_frame_size(10);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+    let text = "fragmented ünicode message";
+    server.send(Message::text(text)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::text(text));
+}
+
+#[tokio::test]
+async fn fragmented_message_too_large_for_buffer() {
+    let (server, client) = tokio::io::duplex(1024);
+    let config =
+        WebSocketConfig { write_buffer_size: 0, max_write_buffer_size: 200, ..Default::default() };
+    let mut server = WebSocketStream::from_raw_socket(server, Role::Server, Some(config))
+        .await
+        .with_max_outgoing_frame_size(50);
+    let mut client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+    // None of the fragments is queued, so the framing stays intact.
+    match server.send(Message::binary(vec![7; 300])).await {
+        Err(Error::WriteBufferFull(message)) => assert_eq!(message, Message::binary(vec![7; 300])),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    server.send(Message::binary(vec![7; 120])).await.unwrap();
+    server.send(Message::text("after")).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::binary(vec![7; 120]));
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::text("after"));
+}